@@ -0,0 +1,178 @@
+use crate::types::{Item, Property};
+
+use std::io::{self, Write};
+
+/// Write a copy of the `base` place with `items` inserted under the instance
+/// at `path`, which is a list of names starting at a service such as Workspace.
+/// Everything in `base` is kept as it was. Folders are created for any part of
+/// `path` that doesn't exist yet.
+pub fn write_into_place<W: Write>(
+    w: &mut W,
+    base: &str,
+    path: &[&str],
+    items: Vec<Item>,
+) -> io::Result<()> {
+    let doc = roxmltree::Document::parse(base)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut parent = doc.root_element();
+    let mut remaining = path;
+    while let Some((name, rest)) = remaining.split_first() {
+        match parent
+            .children()
+            .find(|n| n.has_tag_name("Item") && item_name(n) == Some(name))
+        {
+            Some(child) => {
+                parent = child;
+                remaining = rest;
+            }
+            None => break,
+        }
+    }
+    if remaining.len() == path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("The base place has no top level instance named {}", path[0]),
+        ));
+    }
+
+    // Wrap the items in folders for every part of the path that is missing
+    let mut items = items;
+    for name in remaining.iter().rev() {
+        let mut folder = Item::new("Folder");
+        folder
+            .properties
+            .insert("Name", Property::String(name.to_string()));
+        folder.children = items;
+        items = vec![folder];
+    }
+
+    let closing_tag = "</Item>";
+    let end = parent.range().end;
+    if !base[..end].ends_with(closing_tag) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Could not find the end of {}", path.join("/")),
+        ));
+    }
+    let insert_at = end - closing_tag.len();
+
+    write!(w, "{}", &base[..insert_at])?;
+    for item in items {
+        writeln!(w, "{}", item)?;
+    }
+    write!(w, "{}", &base[insert_at..])
+}
+
+/// The `Name` property of an `Item` element, or its class if it has no name
+fn item_name<'a>(item: &roxmltree::Node<'a, '_>) -> Option<&'a str> {
+    item.children()
+        .find(|n| n.has_tag_name("Properties"))
+        .and_then(|properties| {
+            properties
+                .children()
+                .find(|n| n.is_element() && n.attribute("name") == Some("Name"))
+        })
+        .map(|name| name.text().unwrap_or(""))
+        .or_else(|| item.attribute("class"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"<roblox version="4">
+	<Item class="Workspace" referent="RBX0">
+		<Properties>
+			<string name="Name">Workspace</string>
+		</Properties>
+		<Item class="Model" referent="RBX1">
+			<Properties>
+				<string name="Name">Map</string>
+			</Properties>
+			<Item class="Part" referent="RBX2">
+				<Properties>
+					<string name="Name">Baseplate</string>
+				</Properties>
+			</Item>
+		</Item>
+	</Item>
+	<Item class="Lighting" referent="RBX3">
+		<Properties>
+			<string name="Name">Lighting</string>
+		</Properties>
+	</Item>
+</roblox>"#;
+
+    fn brick() -> Item {
+        let mut part = Item::new("Part");
+        part.properties
+            .insert("Name", Property::String("Brick".to_string()));
+        part
+    }
+
+    fn write(path: &[&str]) -> io::Result<String> {
+        let mut place = vec![];
+        write_into_place(&mut place, BASE, path, vec![brick()])?;
+        Ok(String::from_utf8(place).unwrap())
+    }
+
+    /// The names of the children of the item at `path`
+    fn children(place: &str, path: &[&str]) -> Vec<String> {
+        let doc = roxmltree::Document::parse(place).unwrap();
+        let mut item = doc.root_element();
+        for name in path {
+            item = item
+                .children()
+                .find(|n| n.has_tag_name("Item") && item_name(n) == Some(name))
+                .unwrap_or_else(|| panic!("{} is missing", name));
+        }
+        item.children()
+            .filter(|n| n.has_tag_name("Item"))
+            .map(|n| item_name(&n).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn items_go_after_the_children_of_an_existing_path() {
+        let place = write(&["Workspace", "Map"]).unwrap();
+        assert_eq!(
+            children(&place, &["Workspace", "Map"]),
+            ["Baseplate", "Brick"]
+        );
+        assert_eq!(children(&place, &[]), ["Workspace", "Lighting"]);
+        // Everything else is copied as it was, and the brick goes in right
+        // before the end of Map
+        let map_end = BASE.find("\t\t</Item>\n\t</Item>").unwrap() + 2;
+        assert_eq!(place[..map_end], BASE[..map_end]);
+        assert!(place[map_end..].starts_with("<Item class=\"Part\""));
+        assert!(place.ends_with(&BASE[map_end..]));
+    }
+
+    #[test]
+    fn missing_folders_are_created() {
+        let place = write(&["Workspace", "Map", "Converted", "Bricks"]).unwrap();
+        assert_eq!(
+            children(&place, &["Workspace", "Map"]),
+            ["Baseplate", "Converted"]
+        );
+        assert_eq!(
+            children(&place, &["Workspace", "Map", "Converted"]),
+            ["Bricks"]
+        );
+        assert_eq!(
+            children(&place, &["Workspace", "Map", "Converted", "Bricks"]),
+            ["Brick"]
+        );
+    }
+
+    #[test]
+    fn paths_start_at_a_top_level_instance() {
+        let error = write(&["Map"]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "The base place has no top level instance named Map"
+        );
+    }
+}
@@ -0,0 +1,631 @@
+use crate::types::{
+    Attributes, CFrame, Color3, Color3Float, Content, Item, OptionalCFrame, PhysicalProperties,
+    Property, ProtectedString, RbxUuid, Ref, Tags, Vector3,
+};
+use crate::xml::{END_XML, START_XML};
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::{self, Write},
+};
+
+const MAGIC: &[u8] = b"<roblox!\x89\xff\x0d\x0a\x1a\x0a";
+
+const TYPE_STRING: u8 = 0x01;
+const TYPE_BOOL: u8 = 0x02;
+const TYPE_INT32: u8 = 0x03;
+const TYPE_FLOAT: u8 = 0x04;
+const TYPE_COLOR3: u8 = 0x0C;
+const TYPE_VECTOR3: u8 = 0x0E;
+const TYPE_CFRAME: u8 = 0x10;
+const TYPE_TOKEN: u8 = 0x12;
+const TYPE_REFERENT: u8 = 0x13;
+const TYPE_PHYSICAL_PROPERTIES: u8 = 0x19;
+const TYPE_COLOR3UINT8: u8 = 0x1A;
+const TYPE_OPTIONAL_CFRAME: u8 = 0x1E;
+
+lazy_static! {
+    static ref PLACE_TEMPLATE: String = format!("{}{}", START_XML, END_XML);
+    static ref PLACE_DOCUMENT: roxmltree::Document<'static> =
+        roxmltree::Document::parse(&PLACE_TEMPLATE).unwrap();
+    /// The class and name of every service of the XML place template, so that
+    /// binary places have the same services as XML ones
+    static ref SERVICES: Vec<(&'static str, &'static str)> = PLACE_DOCUMENT
+            .root_element()
+            .children()
+            .filter(|node| node.has_tag_name("Item"))
+            .map(|service| {
+                let name = service
+                    .descendants()
+                    .find(|node| node.attribute("name") == Some("Name"))
+                    .and_then(|node| node.text())
+                    .unwrap();
+                (service.attribute("class").unwrap(), name)
+            })
+            .collect();
+}
+
+/// An instance flattened out of an `Item` tree
+struct Instance<'a> {
+    item: &'a Item,
+    parent: i32,
+}
+
+/// Write `items` as a binary place (.rbxl), parented to Workspace
+pub fn write_place<W: Write>(w: &mut W, items: Vec<Item>) -> io::Result<()> {
+    let mut services = SERVICES
+        .iter()
+        .map(|(class, name)| {
+            let mut service = Item::new(class);
+            service
+                .properties
+                .insert("Name", Property::String(name.to_string()));
+            service
+        })
+        .collect::<Vec<_>>();
+    if let Some(workspace) = services.iter_mut().find(|s| s.class == "Workspace") {
+        workspace.children = items;
+    }
+    write_roots(w, &services)
+}
+
+/// Write every item in `roots` (and their descendants) as a binary file
+pub fn write_roots<W: Write>(w: &mut W, roots: &[Item]) -> io::Result<()> {
+    fn flatten<'a>(item: &'a Item, parent: i32, instances: &mut Vec<Instance<'a>>) {
+        let referent = instances.len() as i32;
+        instances.push(Instance { item, parent });
+        for child in item.children.iter() {
+            flatten(child, referent, instances);
+        }
+    }
+
+    let mut instances = Vec::new();
+    for root in roots {
+        flatten(root, -1, &mut instances);
+    }
+
+    let ids = instances
+        .iter()
+        .enumerate()
+        .map(|(referent, instance)| (&instance.item.referent, referent as i32))
+        .collect::<HashMap<_, _>>();
+
+    // Referents of every instance, grouped by class name
+    let mut classes: BTreeMap<&str, Vec<i32>> = BTreeMap::new();
+    for (referent, instance) in instances.iter().enumerate() {
+        classes
+            .entry(instance.item.class)
+            .or_default()
+            .push(referent as i32);
+    }
+
+    w.write_all(MAGIC)?;
+    w.write_all(&0u16.to_le_bytes())?;
+    w.write_all(&(classes.len() as i32).to_le_bytes())?;
+    w.write_all(&(instances.len() as i32).to_le_bytes())?;
+    w.write_all(&[0; 8])?;
+
+    for (class_id, (class, referents)) in classes.iter().enumerate() {
+        let mut chunk = Vec::new();
+        chunk.extend_from_slice(&(class_id as u32).to_le_bytes());
+        write_string(&mut chunk, class);
+        let is_service = SERVICES.iter().any(|(service, _)| service == class);
+        chunk.push(if is_service { 1 } else { 0 });
+        chunk.extend_from_slice(&(referents.len() as u32).to_le_bytes());
+        write_referents(&mut chunk, referents);
+        if is_service {
+            chunk.extend(referents.iter().map(|_| 1));
+        }
+        write_chunk(w, b"INST", &chunk)?;
+    }
+
+    for (class_id, (class, referents)) in classes.iter().enumerate() {
+        let items = referents
+            .iter()
+            .map(|r| instances[*r as usize].item)
+            .collect::<Vec<_>>();
+        let names = items
+            .iter()
+            .flat_map(|i| i.properties.keys().copied())
+            .collect::<BTreeSet<_>>();
+        for name in names {
+            let template = items.iter().find_map(|i| i.properties.get(name)).unwrap();
+            let mut given = Vec::with_capacity(items.len());
+            for item in items.iter() {
+                match item.properties.get(name) {
+                    Some(p) if std::mem::discriminant(p) != std::mem::discriminant(template) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "{} of a {} is a {}, but a {} on other instances",
+                                name,
+                                class,
+                                p.type_name(),
+                                template.type_name()
+                            ),
+                        ));
+                    }
+                    p => given.push(p),
+                }
+            }
+            // Every instance of a class needs a value, so instances without
+            // the property get the default Roblox gives it
+            let default = default_value(items[0].class, name, template);
+            let values = given
+                .iter()
+                .map(|p| p.unwrap_or(&default))
+                .collect::<Vec<_>>();
+
+            let mut chunk = Vec::new();
+            chunk.extend_from_slice(&(class_id as u32).to_le_bytes());
+            write_string(&mut chunk, name);
+            write_values(&mut chunk, &values, &ids);
+            write_chunk(w, b"PROP", &chunk)?;
+        }
+    }
+
+    let mut chunk = vec![0];
+    chunk.extend_from_slice(&(instances.len() as u32).to_le_bytes());
+    write_referents(&mut chunk, &(0..instances.len() as i32).collect::<Vec<_>>());
+    write_referents(
+        &mut chunk,
+        &instances.iter().map(|i| i.parent).collect::<Vec<_>>(),
+    );
+    write_chunk(w, b"PRNT", &chunk)?;
+
+    // The END chunk is never compressed
+    w.write_all(b"END\0")?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&9u32.to_le_bytes())?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(b"</roblox>")
+}
+
+fn write_chunk<W: Write>(w: &mut W, name: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let compressed = lz4_flex::block::compress(data);
+    w.write_all(name)?;
+    w.write_all(&(compressed.len() as u32).to_le_bytes())?;
+    w.write_all(&(data.len() as u32).to_le_bytes())?;
+    w.write_all(&0u32.to_le_bytes())?;
+    w.write_all(&compressed)
+}
+
+/// The value Roblox gives a property that isn't set, for a property of the
+/// same type as `template`
+fn default_value(class: &str, name: &str, template: &Property) -> Property {
+    match (name, template) {
+        ("Name", Property::String(_)) => return Property::String(class.to_string()),
+        (
+            "Archivable" | "CanCollide" | "CanQuery" | "CanTouch" | "CastShadow" | "Enabled"
+            | "Neutral" | "RequiresHandle" | "CanBeDropped",
+            Property::Bool(_),
+        ) => return Property::Bool(true),
+        ("Color3uint8", Property::Color3(_)) => {
+            return Property::Color3(Color3 {
+                r: 163,
+                g: 162,
+                b: 165,
+                a: 255,
+            })
+        }
+        ("Color", Property::Color3Float(_)) => {
+            return Property::Color3Float(Color3Float {
+                r: 1.,
+                g: 1.,
+                b: 1.,
+            })
+        }
+        ("size", Property::Vector3(_)) => return Property::Vector3(Vector3::new(4., 1.2, 2.)),
+        ("Brightness", Property::Float(_)) => return Property::Float(1.),
+        ("Range", Property::Float(_)) => {
+            return Property::Float(if class == "PointLight" { 8. } else { 16. })
+        }
+        ("Angle", Property::Float(_)) => return Property::Float(90.),
+        ("Duration", Property::Int(_)) => return Property::Int(10),
+        ("Elasticity", Property::Float(_)) => return Property::Float(0.5),
+        ("Friction", Property::Float(_)) => return Property::Float(0.3),
+        ("Material", Property::Token(_)) => return Property::Token(256),
+        // Front
+        ("Face", Property::Token(_)) => return Property::Token(5),
+        _ => {}
+    }
+    match template {
+        Property::Bool(_) => Property::Bool(false),
+        Property::Float(_) => Property::Float(0.),
+        Property::Token(_) => Property::Token(0),
+        Property::Int(_) => Property::Int(0),
+        Property::CFrame(_) => Property::CFrame(CFrame {
+            vector: Vector3::new(0., 0., 0.),
+            rotation: nalgebra::Rotation3::identity(),
+        }),
+        Property::OptionalCFrame(_) => Property::OptionalCFrame(OptionalCFrame(None)),
+        Property::Color3(_) => Property::Color3(Color3 {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 0,
+        }),
+        Property::Color3Float(_) => Property::Color3Float(Color3Float {
+            r: 0.,
+            g: 0.,
+            b: 0.,
+        }),
+        Property::PhysProps(_) => Property::PhysProps(PhysicalProperties(false)),
+        Property::Content(_) => Property::Content(Content(String::new())),
+        Property::ProtectedString(_) => Property::ProtectedString(ProtectedString(String::new())),
+        Property::Ref(_) => Property::Ref(Ref(None)),
+        Property::Tags(_) => Property::Tags(Tags::default()),
+        Property::Attributes(_) => Property::Attributes(Attributes::default()),
+        Property::String(_) => Property::String(String::new()),
+        Property::Vector3(_) => Property::Vector3(Vector3::new(0., 0., 0.)),
+    }
+}
+
+/// Write the type id followed by the values of one property for every instance
+/// of a class. All of `values` must be the same variant. `ids` are the
+/// referents in this file of every item.
+fn write_values(buf: &mut Vec<u8>, values: &[&Property], ids: &HashMap<&RbxUuid, i32>) {
+    macro_rules! collect {
+        ($variant:ident) => {
+            values
+                .iter()
+                .map(|p| match p {
+                    Property::$variant(v) => v,
+                    _ => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+    }
+
+    match values[0] {
+        Property::Bool(_) => {
+            buf.push(TYPE_BOOL);
+            buf.extend(collect!(Bool).into_iter().map(|b| *b as u8));
+        }
+        Property::Float(_) => {
+            buf.push(TYPE_FLOAT);
+            write_floats(buf, collect!(Float).into_iter().copied());
+        }
+        Property::Token(_) => {
+            buf.push(TYPE_TOKEN);
+            write_interleaved(
+                buf,
+                &collect!(Token)
+                    .into_iter()
+                    .map(|t| t.to_be_bytes())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        Property::Int(_) => {
+            buf.push(TYPE_INT32);
+            write_interleaved(
+                buf,
+                &collect!(Int)
+                    .into_iter()
+                    .map(|i| transform_i32(*i as i32).to_be_bytes())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        Property::CFrame(_) => write_cframes(buf, &collect!(CFrame)),
+        Property::OptionalCFrame(_) => {
+            // The CFrames, with unset ones at the origin, followed by whether
+            // each one is set
+            buf.push(TYPE_OPTIONAL_CFRAME);
+            let optionals = collect!(OptionalCFrame);
+            let origin = CFrame {
+                vector: Vector3::new(0., 0., 0.),
+                rotation: nalgebra::Rotation3::identity(),
+            };
+            write_cframes(
+                buf,
+                &optionals
+                    .iter()
+                    .map(|c| c.0.as_ref().unwrap_or(&origin))
+                    .collect::<Vec<_>>(),
+            );
+            buf.push(TYPE_BOOL);
+            buf.extend(optionals.iter().map(|c| c.0.is_some() as u8));
+        }
+        Property::Color3(_) => {
+            buf.push(TYPE_COLOR3UINT8);
+            let colors = collect!(Color3);
+            buf.extend(colors.iter().map(|c| c.r));
+            buf.extend(colors.iter().map(|c| c.g));
+            buf.extend(colors.iter().map(|c| c.b));
+        }
+        Property::Color3Float(_) => {
+            buf.push(TYPE_COLOR3);
+            let colors = collect!(Color3Float);
+            write_floats(buf, colors.iter().map(|c| c.r));
+            write_floats(buf, colors.iter().map(|c| c.g));
+            write_floats(buf, colors.iter().map(|c| c.b));
+        }
+        Property::PhysProps(_) => {
+            buf.push(TYPE_PHYSICAL_PROPERTIES);
+            for props in collect!(PhysProps) {
+                // Custom physical properties aren't supported, so there are
+                // never any values following the flag
+                debug_assert!(!props.0);
+                buf.push(0);
+            }
+        }
+        Property::Content(_) => {
+            buf.push(TYPE_STRING);
+            for content in collect!(Content) {
+                write_string(buf, &content.0);
+            }
+        }
+        Property::ProtectedString(_) => {
+            buf.push(TYPE_STRING);
+            for source in collect!(ProtectedString) {
+                write_string(buf, &source.0);
+            }
+        }
+        Property::Ref(_) => {
+            buf.push(TYPE_REFERENT);
+            // Items that aren't part of the file can't be referred to
+            let referents = collect!(Ref)
+                .into_iter()
+                .map(|r| r.0.as_ref().and_then(|r| ids.get(r)).copied().unwrap_or(-1))
+                .collect::<Vec<_>>();
+            write_referents(buf, &referents);
+        }
+        Property::Tags(_) => {
+            buf.push(TYPE_STRING);
+            for tags in collect!(Tags) {
+                write_bytes(buf, &tags.to_bytes());
+            }
+        }
+        Property::Attributes(_) => {
+            buf.push(TYPE_STRING);
+            for attributes in collect!(Attributes) {
+                write_bytes(buf, &attributes.to_bytes());
+            }
+        }
+        Property::String(_) => {
+            buf.push(TYPE_STRING);
+            for s in collect!(String) {
+                write_string(buf, s);
+            }
+        }
+        Property::Vector3(_) => {
+            buf.push(TYPE_VECTOR3);
+            let vectors = collect!(Vector3);
+            write_floats(buf, vectors.iter().map(|v| v.x()));
+            write_floats(buf, vectors.iter().map(|v| v.y()));
+            write_floats(buf, vectors.iter().map(|v| v.z()));
+        }
+    }
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    write_bytes(buf, s.as_bytes());
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+/// Write big endian values so that the first bytes of every value come first,
+/// then the second bytes, and so on
+fn write_interleaved<const N: usize>(buf: &mut Vec<u8>, values: &[[u8; N]]) {
+    for byte in 0..N {
+        buf.extend(values.iter().map(|v| v[byte]));
+    }
+}
+
+fn write_cframes(buf: &mut Vec<u8>, cframes: &[&CFrame]) {
+    buf.push(TYPE_CFRAME);
+    for cframe in cframes.iter() {
+        // Rotation id 0 means the full matrix follows
+        buf.push(0);
+        for row in 0..3 {
+            for column in 0..3 {
+                buf.extend_from_slice(&cframe.rotation[(row, column)].to_le_bytes());
+            }
+        }
+    }
+    write_floats(buf, cframes.iter().map(|c| c.vector.x()));
+    write_floats(buf, cframes.iter().map(|c| c.vector.y()));
+    write_floats(buf, cframes.iter().map(|c| c.vector.z()));
+}
+
+fn write_floats(buf: &mut Vec<u8>, floats: impl Iterator<Item = f32>) {
+    write_interleaved(
+        buf,
+        &floats
+            .map(|f| f.to_bits().rotate_left(1).to_be_bytes())
+            .collect::<Vec<_>>(),
+    );
+}
+
+/// Referent arrays are delta encoded before being transformed and interleaved
+fn write_referents(buf: &mut Vec<u8>, referents: &[i32]) {
+    let mut last = 0;
+    write_interleaved(
+        buf,
+        &referents
+            .iter()
+            .map(|r| {
+                let delta = r - last;
+                last = *r;
+                transform_i32(delta).to_be_bytes()
+            })
+            .collect::<Vec<_>>(),
+    );
+}
+
+fn transform_i32(i: i32) -> u32 {
+    ((i << 1) ^ (i >> 31)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The chunks of a binary file, decompressed, by name
+    fn chunks(file: &[u8]) -> Vec<([u8; 4], Vec<u8>)> {
+        let u32_at =
+            |i: usize| u32::from_le_bytes([file[i], file[i + 1], file[i + 2], file[i + 3]]);
+        let mut chunks = vec![];
+        let mut i = MAGIC.len() + 2 + 4 + 4 + 8;
+        loop {
+            let name = [file[i], file[i + 1], file[i + 2], file[i + 3]];
+            let (compressed, size) = (u32_at(i + 4) as usize, u32_at(i + 8) as usize);
+            i += 16;
+            if &name == b"END\0" {
+                return chunks;
+            }
+            let data = lz4_flex::block::decompress(&file[i..i + compressed], size).unwrap();
+            chunks.push((name, data));
+            i += compressed;
+        }
+    }
+
+    fn read_string(data: &[u8], i: &mut usize) -> String {
+        let len = u32::from_le_bytes([data[*i], data[*i + 1], data[*i + 2], data[*i + 3]]) as usize;
+        *i += 4;
+        let s = String::from_utf8(data[*i..*i + len].to_vec()).unwrap();
+        *i += len;
+        s
+    }
+
+    /// The type and values of a property of every instance of a class
+    fn property(file: &[u8], class: &str, property: &str) -> (u8, Vec<u8>) {
+        let chunks = chunks(file);
+        let class_id = chunks
+            .iter()
+            .filter(|(name, _)| name == b"INST")
+            .find_map(|(_, data)| {
+                let mut i = 4;
+                (read_string(data, &mut i) == class).then(|| data[..4].to_vec())
+            })
+            .unwrap();
+        chunks
+            .iter()
+            .filter(|(name, data)| name == b"PROP" && data[..4] == class_id[..])
+            .find_map(|(_, data)| {
+                let mut i = 4;
+                (read_string(data, &mut i) == property).then(|| (data[i], data[i + 1..].to_vec()))
+            })
+            .unwrap()
+    }
+
+    fn strings(values: &[u8]) -> Vec<String> {
+        let mut i = 0;
+        let mut strings = vec![];
+        while i < values.len() {
+            strings.push(read_string(values, &mut i));
+        }
+        strings
+    }
+
+    fn named(class: &'static str, name: Option<&str>) -> Item {
+        let mut item = Item::new(class);
+        if let Some(name) = name {
+            item.properties
+                .insert("Name", Property::String(name.to_string()));
+        }
+        item
+    }
+
+    #[test]
+    fn missing_properties_get_roblox_defaults() {
+        let mut named_part = named("Part", Some("Door"));
+        named_part
+            .properties
+            .insert("CanCollide", Property::Bool(false));
+        named_part.properties.insert(
+            "Color3uint8",
+            Property::Color3(Color3 {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            }),
+        );
+        let mut pivoted = named("Model", Some("Chunk 0, 0"));
+        pivoted.properties.insert(
+            "WorldPivot",
+            Property::OptionalCFrame(OptionalCFrame(Some(CFrame {
+                vector: Vector3::new(1., 2., 3.),
+                rotation: nalgebra::Rotation3::identity(),
+            }))),
+        );
+        let mut group = named("Model", None);
+        group.children = vec![named_part, named("Part", None)];
+
+        let mut file = vec![];
+        write_roots(&mut file, &[pivoted, group]).unwrap();
+
+        let (kind, names) = property(&file, "Part", "Name");
+        assert_eq!(kind, TYPE_STRING);
+        assert_eq!(strings(&names), ["Door", "Part"]);
+        assert_eq!(
+            strings(&property(&file, "Model", "Name").1),
+            ["Chunk 0, 0", "Model"]
+        );
+        assert_eq!(
+            property(&file, "Part", "CanCollide"),
+            (TYPE_BOOL, vec![0, 1])
+        );
+        assert_eq!(
+            property(&file, "Part", "Color3uint8"),
+            (TYPE_COLOR3UINT8, vec![255, 163, 0, 162, 0, 165])
+        );
+
+        // The unset pivot is marked as missing instead of being at the origin
+        let (kind, pivots) = property(&file, "Model", "WorldPivot");
+        assert_eq!(kind, TYPE_OPTIONAL_CFRAME);
+        assert_eq!(pivots[0], TYPE_CFRAME);
+        assert_eq!(pivots[pivots.len() - 3..], [TYPE_BOOL, 1, 0]);
+    }
+
+    #[test]
+    fn mismatched_properties_are_errors() {
+        let mut named_part = named("Part", Some("Door"));
+        let mut numbered = named("Part", None);
+        numbered.properties.insert("Name", Property::Int(1));
+        named_part.children.push(numbered);
+
+        let error = write_roots(&mut vec![], &[named_part]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "Name of a Part is a int, but a string on other instances"
+        );
+    }
+
+    #[test]
+    fn places_have_the_services_of_xml_places() {
+        let mut file = vec![];
+        write_place(&mut file, vec![named("Part", Some("Brick"))]).unwrap();
+
+        assert_eq!(
+            strings(&property(&file, "Workspace", "Name").1),
+            ["Workspace"]
+        );
+        assert_eq!(
+            strings(&property(&file, "Lighting", "Name").1),
+            ["Lighting"]
+        );
+        assert_eq!(
+            strings(&property(&file, "TeleportService", "Name").1),
+            ["Teleport Service"]
+        );
+        let services = chunks(&file)
+            .into_iter()
+            .filter(|(name, data)| {
+                name == b"INST" && {
+                    let mut i = 4;
+                    read_string(data, &mut i);
+                    data[i] == 1
+                }
+            })
+            .count();
+        // Every top level item of the XML template, Camera and Terrain are in
+        // Workspace
+        assert_eq!(services, PLACE_TEMPLATE.matches("\n\t<Item ").count());
+    }
+}
@@ -0,0 +1,415 @@
+//! Bricks from Blockland's own .blb brick files, for add-on bricks that aren't
+//! built into the converter.
+//!
+//! A .blb file starts with the size of the brick (in studs, studs and plates)
+//! and whether it's a plain `BRICK` or a `SPECIAL` one. Special bricks list
+//! collision boxes, which are turned into Parts. The face quads are ignored.
+//!
+//! UI names are read from the `fxDTSBrickData` datablocks in the .cs files next
+//! to the .blb files. A .blb file that no datablock uses is named after its
+//! file name instead.
+
+use crate::error::{Error, Result};
+use crate::types::{CFrame, Item, Property, Vector3};
+use crate::BRICK_HEIGHT;
+
+use nalgebra::Rotation3;
+use regex::Regex;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+lazy_static! {
+    static ref DATABLOCK_RE: Regex =
+        Regex::new(r"(?is)datablock\s+fxDTSBrickData\s*\([^)]*\)\s*\{(.*?)\}").unwrap();
+    static ref BRICK_FILE_RE: Regex = Regex::new(r#"(?i)\bbrickFile\s*=\s*"([^"]*)""#).unwrap();
+    static ref UI_NAME_RE: Regex = Regex::new(r#"(?i)\buiName\s*=\s*"([^"]*)""#).unwrap();
+}
+
+/// A box inside a brick, measured like the brick itself
+#[derive(Clone)]
+pub struct CollisionBox {
+    pub center: (f32, f32, f32),
+    pub size: (f32, f32, f32),
+}
+
+/// The parts of a .blb file the converter uses
+#[derive(Clone)]
+pub struct BlbBrick {
+    /// Width and length in studs, height in plates
+    pub size: (u32, u32, u32),
+    /// Empty for plain bricks
+    pub collision: Vec<CollisionBox>,
+}
+
+/// Parse the contents of a .blb file
+pub fn parse_blb(source: &str) -> std::result::Result<BlbBrick, String> {
+    fn numbers<T: std::str::FromStr>(line: &str) -> Option<(T, T, T)> {
+        let mut split = line.split_whitespace().map(str::parse);
+        match (split.next(), split.next(), split.next(), split.next()) {
+            (Some(Ok(x)), Some(Ok(y)), Some(Ok(z)), None) => Some((x, y, z)),
+            _ => None,
+        }
+    }
+
+    let mut lines = source.lines().map(str::trim);
+    let size = lines
+        .next()
+        .and_then(numbers)
+        .ok_or_else(|| "the first line should be the size of the brick".to_string())?;
+    if size.0 == 0 || size.1 == 0 || size.2 == 0 {
+        return Err("size can't be zero".to_string());
+    }
+    let special = match lines.next() {
+        Some("BRICK") => false,
+        Some("SPECIAL") => true,
+        _ => return Err("the second line should be BRICK or SPECIAL".to_string()),
+    };
+
+    let mut collision = vec![];
+    if special {
+        // Skip the brick grid, the collision box count is the first line that
+        // is only a number
+        let count = lines
+            .by_ref()
+            .find_map(|line| line.parse::<usize>().ok())
+            .ok_or_else(|| "missing collision box count".to_string())?;
+        let mut values = lines.filter(|line| !line.is_empty());
+        for _ in 0..count {
+            let (center, size) = match (values.next(), values.next()) {
+                (Some(center), Some(size)) => (numbers(center), numbers(size)),
+                _ => return Err("missing collision boxes".to_string()),
+            };
+            match (center, size) {
+                (Some(center), Some(size)) => collision.push(CollisionBox { center, size }),
+                _ => return Err("invalid collision box".to_string()),
+            }
+        }
+    }
+    Ok(BlbBrick { size, collision })
+}
+
+/// Size of a brick or box in studs, in Roblox axes
+fn roblox_size((x, y, z): (f32, f32, f32)) -> Vector3 {
+    Vector3::new(x, z * BRICK_HEIGHT / 3., y)
+}
+
+/// Bricks loaded from .blb files, by UI name
+#[derive(Default)]
+pub struct BlbBricks {
+    bricks: HashMap<String, BlbBrick>,
+    ambiguous: Vec<String>,
+}
+
+impl BlbBricks {
+    pub fn new() -> Self {
+        BlbBricks::default()
+    }
+
+    /// Load a .blb file, an add-on .zip archive, or a directory containing
+    /// either of them
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let mut files = vec![];
+        if path.is_dir() {
+            self.read_dir(path, path, &mut files)?;
+        } else if has_extension(path, "zip") {
+            self.read_zip(path)?;
+        } else {
+            let name = file_name(path);
+            files.push((name, decode_cp1252(&fs::read(path)?)));
+        }
+        self.add_files(files, &path.display().to_string())
+    }
+
+    fn read_dir(
+        &mut self,
+        root: &Path,
+        dir: &Path,
+        files: &mut Vec<(String, String)>,
+    ) -> Result<()> {
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
+        for path in entries {
+            if path.is_dir() {
+                self.read_dir(root, &path, files)?;
+            } else if has_extension(&path, "zip") {
+                self.read_zip(&path)?;
+            } else if has_extension(&path, "blb") || has_extension(&path, "cs") {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                let name = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.push((name, decode_cp1252(&fs::read(&path)?)));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_zip(&mut self, path: &Path) -> Result<()> {
+        let origin = path.display().to_string();
+        let error = |e: zip::result::ZipError| Error::Definition {
+            origin: origin.clone(),
+            reason: e.to_string(),
+        };
+        let mut archive = zip::ZipArchive::new(File::open(path)?).map_err(error)?;
+        let mut files = vec![];
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).map_err(error)?;
+            let name = file.name().to_string();
+            if has_extension(Path::new(&name), "blb") || has_extension(Path::new(&name), "cs") {
+                let mut contents = vec![];
+                file.read_to_end(&mut contents)?;
+                files.push((name, decode_cp1252(&contents)));
+            }
+        }
+        self.add_files(files, &origin)
+    }
+
+    /// Add the bricks in a set of .blb and .cs files, named by their path in
+    /// the add-on
+    fn add_files(&mut self, files: Vec<(String, String)>, origin: &str) -> Result<()> {
+        let mut blbs = BTreeMap::new();
+        let mut scripts = vec![];
+        for (name, contents) in files {
+            if has_extension(Path::new(&name), "blb") {
+                let brick = parse_blb(&contents).map_err(|reason| Error::Definition {
+                    origin: format!("{} ({})", origin, name),
+                    reason,
+                })?;
+                blbs.insert(normalize(&name), (name, brick));
+            } else {
+                scripts.push((name, contents));
+            }
+        }
+
+        let mut ui_names = vec![];
+        for (name, contents) in scripts.iter() {
+            let dir = match name.rfind('/') {
+                Some(i) => &name[..i],
+                None => "",
+            };
+            for datablock in DATABLOCK_RE.captures_iter(contents) {
+                let body = &datablock[1];
+                if let (Some(brick_file), Some(ui_name)) =
+                    (BRICK_FILE_RE.captures(body), UI_NAME_RE.captures(body))
+                {
+                    let brick_file = &brick_file[1];
+                    let path = match brick_file.strip_prefix("./") {
+                        Some(relative) => normalize(&format!("{}/{}", dir, relative)),
+                        None => normalize(brick_file),
+                    };
+                    ui_names.push((ui_name[1].to_string(), path));
+                }
+            }
+        }
+
+        // Add bricks with a UI name first, and name the rest after their file
+        let mut used = vec![];
+        for (ui_name, path) in ui_names {
+            let key = if blbs.contains_key(&path) {
+                Some(path)
+            } else {
+                // Paths can be written from another add-on's folder, so fall
+                // back to files whose paths end the same way
+                let matches = blbs
+                    .keys()
+                    .filter(|key| {
+                        path.ends_with(&format!("/{}", key)) || key.ends_with(&format!("/{}", path))
+                    })
+                    .collect::<Vec<_>>();
+                if matches.len() > 1 {
+                    self.ambiguous.push(format!(
+                        "{} in {}: could be any of {}, using {}",
+                        ui_name,
+                        origin,
+                        matches
+                            .iter()
+                            .map(|key| blbs[*key].0.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        blbs[matches[0]].0,
+                    ));
+                }
+                matches.first().map(|key| key.to_string())
+            };
+            if let Some(key) = key {
+                used.push((ui_name, key));
+            }
+        }
+        for (ui_name, key) in used.iter() {
+            if let Some((_, brick)) = blbs.get(key) {
+                self.bricks.insert(ui_name.clone(), brick.clone());
+            }
+        }
+        for (key, (name, brick)) in blbs {
+            if used.iter().all(|(_, used_key)| *used_key != key) {
+                if let Some(stem) = Path::new(&name).file_stem() {
+                    self.bricks
+                        .insert(stem.to_string_lossy().into_owned(), brick);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bricks whose .blb file couldn't be told apart from others with paths
+    /// that end the same way. The first path in order is used for them.
+    pub fn ambiguous(&self) -> &[String] {
+        &self.ambiguous
+    }
+
+    pub fn len(&self) -> usize {
+        self.bricks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bricks.is_empty()
+    }
+
+    /// Build the brick with this UI name around the origin, in studs: a .blb
+    /// stud is one stud and a plate is a third of a brick's height. The
+    /// converter scales and places it like any other brick. Plain bricks are a
+    /// single Part, bricks with collision boxes are a Model with a Part for
+    /// every box.
+    pub fn generate(&self, ui_name: &str) -> Option<Item> {
+        let brick = self.bricks.get(ui_name)?;
+        let part = |center: (f32, f32, f32), size: (f32, f32, f32)| {
+            let mut part = Item::default("Part");
+            part.properties
+                .insert("size", Property::Vector3(roblox_size(size)));
+            part.properties.insert(
+                "CFrame",
+                Property::CFrame(CFrame {
+                    vector: Vector3::new(center.0, center.2 * BRICK_HEIGHT / 3., -center.1),
+                    rotation: Rotation3::identity(),
+                }),
+            );
+            part
+        };
+        let (x, y, z) = brick.size;
+        if brick.collision.is_empty() {
+            Some(part((0., 0., 0.), (x as f32, y as f32, z as f32)))
+        } else {
+            let mut model = Item::default("Model");
+            model.children = brick
+                .collision
+                .iter()
+                .map(|b| part(b.center, b.size))
+                .collect();
+            Some(model)
+        }
+    }
+}
+
+/// Blockland's files are in Windows-1252, which only differs from Latin-1 in
+/// these characters for bytes 0x80 to 0x9F. Bytes that Windows-1252 leaves
+/// undefined are kept as the control characters Latin-1 gives them.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{0081}', '\u{201a}', '\u{0192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008d}', '\u{017d}', '\u{008f}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}', '\u{0153}', '\u{009d}', '\u{017e}', '\u{0178}',
+];
+
+/// Decode a file the way `bl_save` decodes saves
+fn decode_cp1252(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x80..=0x9f => CP1252_HIGH[(b - 0x80) as usize],
+            _ => b as char,
+        })
+        .collect()
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Lowercase a path and remove `.` and `..` parts, since paths in Blockland
+/// scripts are case insensitive
+fn normalize(path: &str) -> String {
+    let mut parts: Vec<&str> = vec![];
+    for part in path.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    parts.join("/").to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_cp1252() {
+        assert_eq!(decode_cp1252(b"45\xb0 Ramp"), "45° Ramp");
+        assert_eq!(decode_cp1252(b"\x80 \x93x\x94"), "€ “x”");
+    }
+
+    #[test]
+    fn loads_cp1252_datablocks() {
+        let dir = std::env::temp_dir().join(format!("bls2rbxlx-blb-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ramp.blb"), b"2 2 3\nBRICK\n").unwrap();
+        fs::write(
+            dir.join("server.cs"),
+            b"datablock fxDTSBrickData(brickRampData)\n{\n\tbrickFile = \"./ramp.blb\";\n\tuiName = \"45\xb0 Test Ramp\";\n};\n",
+        )
+        .unwrap();
+
+        let mut bricks = BlbBricks::new();
+        let result = bricks.load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        result.unwrap();
+        assert_eq!(bricks.len(), 1);
+        assert!(bricks.generate("45° Test Ramp").is_some());
+    }
+
+    #[test]
+    fn prefers_exact_blb_paths() {
+        let files = vec![
+            ("b/shared/ramp.blb".to_string(), "1 1 3\nBRICK\n".to_string()),
+            ("a/shared/ramp.blb".to_string(), "2 2 3\nBRICK\n".to_string()),
+            ("shared/ramp.blb".to_string(), "4 4 3\nBRICK\n".to_string()),
+            (
+                "shared/server.cs".to_string(),
+                "datablock fxDTSBrickData(a) { brickFile = \"./ramp.blb\"; uiName = \"Exact\"; };"
+                    .to_string(),
+            ),
+            (
+                "c/server.cs".to_string(),
+                "datablock fxDTSBrickData(b) { brickFile = \"shared/ramp.blb\"; uiName = \"Also exact\"; };\n\
+                 datablock fxDTSBrickData(c) { brickFile = \"ramp.blb\"; uiName = \"Suffix\"; };"
+                    .to_string(),
+            ),
+        ];
+        let mut bricks = BlbBricks::new();
+        bricks.add_files(files, "test").unwrap();
+        assert_eq!(bricks.bricks["Exact"].size, (4, 4, 3));
+        assert_eq!(bricks.bricks["Also exact"].size, (4, 4, 3));
+        // Ambiguous, so the first path in order is used and reported
+        assert_eq!(bricks.bricks["Suffix"].size, (2, 2, 3));
+        assert_eq!(bricks.ambiguous().len(), 1);
+    }
+}
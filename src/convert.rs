@@ -0,0 +1,2021 @@
+use crate::blb::BlbBricks;
+use crate::definitions::BrickDefinitions;
+use crate::error::{Error, Result};
+use crate::events;
+use crate::extras::{Extras, ItemExtra};
+use crate::fx::{self, ColorFxTable, ShapeFxOptions};
+use crate::grouping::{self, OwnerGrouping};
+use crate::lights::LightTable;
+use crate::optimize;
+use crate::prints::PrintMap;
+use crate::save::SaveReader;
+use crate::specialbricks::{self, SpecialBricksCache};
+use crate::types::{
+	AttributeValue, CFrame, Color3, Content, Item, NormalId, Property, RbxUuid, Vector3,
+};
+use crate::{BRICK_HEIGHT, WEDGE_LIP_SIZE};
+
+use regex::Regex;
+
+use std::{
+	collections::{HashMap, HashSet},
+	io,
+};
+
+lazy_static! {
+	static ref TALL_BRICK_RE: Regex = Regex::new(r"^(\d+)x(\d+)x(\d+)( Print)?$").unwrap();
+	static ref REGULAR_BRICK_RE: Regex = Regex::new(r"^(\d+?)x(\d+)(F| Base)?( Round)?( Print)?$").unwrap();
+	static ref RAMP_BRICK_RE: Regex = Regex::new(r"^(-)?(\d+)° Ramp (\d+)x( Print)?$").unwrap();
+	static ref CORNER_RAMP_BRICK_RE: Regex = Regex::new(r"^(-)?(\d+)° Ramp Corner$").unwrap();
+	static ref CUBE_BRICK_RE: Regex = Regex::new(r"^(\d+)x Cube( Steep)?$").unwrap();
+	static ref ARCH_BRICK_RE: Regex = Regex::new(r"^1x(\d+) Arch$").unwrap();
+	static ref POLE_BRICK_RE: Regex = Regex::new(r"^1x1(F|x(\d+))? Pole$").unwrap();
+	static ref TERRAIN_BRICK_RE: Regex =
+		Regex::new(r"^(\d+)x (Wedge|Ramp|Corner ?[ABCD])( Steep)?$").unwrap();
+	static ref CREST_BRICK_RE: Regex = Regex::new(r"(25|45)° Crest (\d)x").unwrap();
+}
+
+/// What to do when a brick can't be read or converted
+#[derive(Clone, Copy, PartialEq)]
+pub enum ErrorPolicy {
+	/// Stop the conversion and return the error
+	Abort,
+	/// Leave the brick out and list the error in the report
+	SkipAndReport,
+}
+
+/// What to do with bricks that are hidden inside of other bricks
+#[derive(Clone, Copy, PartialEq)]
+pub enum CullMode {
+	/// Leave them out
+	Remove,
+	/// Make them invisible and not solid, and move them into a Folder
+	Hide,
+}
+
+impl std::str::FromStr for CullMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+		match s {
+			"remove" => Ok(CullMode::Remove),
+			"hide" => Ok(CullMode::Hide),
+			_ => Err(format!("Unknown cull mode: {}", s)),
+		}
+	}
+}
+
+/// Settings that change how bricks are converted
+pub struct ConvertOptions {
+	/// How many Roblox studs a Blockland unit will correspond to
+	pub scale: f32,
+	pub error_policy: ErrorPolicy,
+	/// Bricks loaded from definition files. These are checked before the built in brick types.
+	pub definitions: BrickDefinitions,
+	/// Bricks loaded from .blb files. These are used for bricks that aren't otherwise known.
+	pub blb_bricks: BlbBricks,
+	/// Textures for the prints on print bricks
+	pub prints: PrintMap,
+	/// Light datablocks, for bricks with lights
+	pub lights: LightTable,
+	/// Materials and scripts for color effects
+	pub color_fx: ColorFxTable,
+	/// How undulo and water bricks are converted
+	pub shape_fx: ShapeFxOptions,
+	/// Whether to store where each brick came from in attributes
+	pub brick_attributes: bool,
+	/// Put bricks into a group for each owner, instead of returning them directly
+	pub owner_grouping: Option<OwnerGrouping>,
+	/// Split bricks into Models for square cells of this many studs
+	pub chunk_size: Option<f32>,
+	/// Merge neighboring regular bricks that look and act the same into bigger parts
+	pub merge_bricks: bool,
+	/// Cull bricks that are completely covered by other bricks
+	pub cull: Option<CullMode>,
+	/// Leave out bricks that are exact copies of an earlier brick in the same place
+	pub remove_duplicates: bool,
+	/// List bricks that partly overlap each other in the report
+	pub find_overlaps: bool,
+}
+
+impl Default for ConvertOptions {
+	fn default() -> Self {
+		ConvertOptions {
+			scale: 1.,
+			error_policy: ErrorPolicy::Abort,
+			definitions: BrickDefinitions::new(),
+			blb_bricks: BlbBricks::new(),
+			prints: PrintMap::new(),
+			lights: LightTable::new(),
+			color_fx: ColorFxTable::new(),
+			shape_fx: ShapeFxOptions::default(),
+			brick_attributes: false,
+			owner_grouping: None,
+			chunk_size: None,
+			merge_bricks: false,
+			cull: None,
+			remove_duplicates: false,
+			find_overlaps: false,
+		}
+	}
+}
+
+/// Information about a finished conversion
+#[derive(Default)]
+pub struct Report {
+	/// How many bricks were read from the save, whether or not they could be
+	/// converted
+	pub brick_count: usize,
+	/// UI names of bricks that could not be converted
+	pub unknown_bricks: HashSet<String>,
+	/// Errors for bricks that were left out because of `ErrorPolicy::SkipAndReport`
+	pub skipped: Vec<Error>,
+	/// Prints that have no texture in the print map. These bricks have no Decal.
+	pub unmapped_prints: HashSet<String>,
+	/// Light datablocks that aren't in the light table. These bricks have no light.
+	pub unknown_lights: HashSet<String>,
+	/// Inputs, outputs and targets of events that could not be converted
+	pub unsupported_events: HashSet<String>,
+	/// Names that events target but no brick has
+	pub missing_targets: HashSet<String>,
+	/// How many parts there were before and after merging bricks, if they were merged
+	pub merged_parts: Option<PartCounts>,
+	/// How many parts were culled because they were covered by other bricks, if bricks were culled
+	pub culled_parts: Option<usize>,
+	/// How many bricks were left out because an identical brick was in the same place
+	pub duplicates: usize,
+	/// Pairs of bricks that partly overlap, if overlapping bricks were looked for
+	pub overlaps: Vec<(BrickLocation, BrickLocation)>,
+}
+
+/// A brick and where it is in the save
+#[derive(Clone)]
+pub struct BrickLocation {
+	pub ui_name: String,
+	/// Position in Blockland units
+	pub position: (f32, f32, f32),
+}
+
+impl std::fmt::Display for BrickLocation {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let (x, y, z) = self.position;
+		write!(f, "{} at ({} {} {})", self.ui_name, x, y, z)
+	}
+}
+
+/// Numbers of parts before and after a pass over the converted bricks
+#[derive(Clone, Copy)]
+pub struct PartCounts {
+	pub before: usize,
+	pub after: usize,
+}
+
+/// The result of converting a save
+pub struct Conversion {
+	/// Converted items, ready to be parented to Workspace or a Model
+	pub items: Vec<Item>,
+	pub report: Report,
+}
+
+/// The items of a single brick, with what is needed to organize them
+pub(crate) struct ConvertedBrick {
+	/// BL_ID of the player who owns the brick
+	pub owner: Option<u32>,
+	pub ui_name: String,
+	/// Position in the save, in Blockland units
+	pub position: (f32, f32, f32),
+	pub angle: u8,
+	pub color_index: u8,
+	pub items: Vec<Item>,
+}
+
+/// Converts Blockland bricks to Roblox items
+pub struct Converter {
+	options: ConvertOptions,
+	cache: SpecialBricksCache,
+	report: Report,
+	/// Referents of the bricks with each name, for events that target them
+	named: HashMap<String, Vec<RbxUuid>>,
+	/// Whether any converted brick has parts tagged to cycle through colors
+	cycles_colors: bool,
+	/// The ModuleScript every events script requires, once a brick has events
+	event_helpers: Option<Item>,
+}
+
+impl Converter {
+	pub fn new(options: ConvertOptions) -> Self {
+		Converter {
+			options,
+			cache: SpecialBricksCache::new(),
+			report: Report::default(),
+			named: HashMap::new(),
+			cycles_colors: false,
+			event_helpers: None,
+		}
+	}
+
+	/// Convert every brick in a save file
+	pub fn convert_reader<R: io::BufRead>(&mut self, reader: SaveReader<R>) -> Result<Conversion> {
+		let colors = *reader.colors();
+		self.convert_bricks(reader, &colors)
+	}
+
+	/// Convert bricks using the given colorset. Unknown brick types are listed
+	/// in the report, other errors are handled according to the `ErrorPolicy`.
+	pub fn convert_bricks(
+		&mut self,
+		bricks: impl IntoIterator<Item = Result<bl_save::Brick>>,
+		colors: &[(f32, f32, f32, f32); 64],
+	) -> Result<Conversion> {
+		let mut converted = Vec::new();
+		for brick in bricks {
+			let result = brick.and_then(|brick| self.convert_brick_with_owner(&brick, colors));
+			match result {
+				Ok(brick) => converted.push(brick),
+				Err(Error::UnsupportedBrick(ui_name)) => {
+					self.report.unknown_bricks.insert(ui_name);
+				}
+				Err(e) => {
+					if e.is_recoverable() && self.options.error_policy == ErrorPolicy::SkipAndReport {
+						self.report.skipped.push(e);
+					} else {
+						return Err(e);
+					}
+				}
+			}
+		}
+		if self.options.remove_duplicates {
+			let (kept, duplicates) = optimize::remove_duplicates(converted);
+			converted = kept;
+			self.report.duplicates = duplicates.len();
+			self.forget_names(&duplicates);
+		}
+		if self.options.find_overlaps {
+			self.report.overlaps = optimize::find_overlaps(&converted, self.options.scale);
+		}
+		let mut culled = vec![];
+		if let Some(mode) = self.options.cull {
+			let (kept, enclosed) = optimize::cull_bricks(converted, self.options.scale);
+			converted = kept;
+			self.report.culled_parts = Some(
+				enclosed
+					.iter()
+					.map(|brick| optimize::count_parts(&brick.items))
+					.sum(),
+			);
+			if mode == CullMode::Hide {
+				culled = enclosed;
+			}
+		}
+		if self.options.merge_bricks {
+			let count = |bricks: &[ConvertedBrick]| {
+				bricks.iter().map(|brick| optimize::count_parts(&brick.items)).sum()
+			};
+			let before = count(&converted);
+			converted = optimize::merge_bricks(converted, self.options.scale);
+			self.report.merged_parts = Some(PartCounts {
+				before,
+				after: count(&converted),
+			});
+		}
+		for brick in converted.iter_mut() {
+			self.resolve_targets(&mut brick.items);
+		}
+		let mut items = match &self.options.owner_grouping {
+			Some(grouping) => grouping.group(
+				converted
+					.into_iter()
+					.map(|brick| (brick.owner, brick.items))
+					.collect(),
+				self.options.chunk_size,
+			),
+			None => {
+				let items = converted.into_iter().flat_map(|brick| brick.items).collect();
+				match self.options.chunk_size {
+					Some(size) => grouping::chunk(items, size),
+					None => items,
+				}
+			}
+		};
+		if !culled.is_empty() {
+			items.push(hidden_folder(culled));
+		}
+		if std::mem::take(&mut self.cycles_colors) {
+			items.push(fx::color_cycle_script());
+		}
+		if let Some(helpers) = self.event_helpers.take() {
+			items.push(helpers);
+		}
+		Ok(Conversion {
+			items,
+			report: self.take_report(),
+		})
+	}
+
+	/// Convert a single brick. Things worth reporting, like prints without a
+	/// texture, are added to the report. Parts of bricks that cycle through
+	/// colors are only tagged, the place also needs one
+	/// `fx::color_cycle_script`, which `convert_bricks` adds itself. The same
+	/// goes for the `events::helpers_module` that events scripts require.
+	pub fn convert_brick(
+		&mut self,
+		brick: &bl_save::Brick,
+		colors: &[(f32, f32, f32, f32); 64],
+	) -> Result<Vec<Item>> {
+		self.convert_brick_with_owner(brick, colors)
+			.map(|brick| brick.items)
+	}
+
+	fn convert_brick_with_owner(
+		&mut self,
+		brick: &bl_save::Brick,
+		colors: &[(f32, f32, f32, f32); 64],
+	) -> Result<ConvertedBrick> {
+		let index = self.report.brick_count;
+		self.report.brick_count += 1;
+		let mut items = items_from_brick(&brick.base, colors, &self.options, &mut self.cache)?;
+		// Special bricks are copies, but events need to tell bricks apart
+		for item in items.iter_mut() {
+			item.renew_referents();
+		}
+		if let Some((face, item)) = print_face(&brick.base, &mut items) {
+			match self.options.prints.texture(&brick.base.print) {
+				Some(texture) => item.children.push(decal(&brick.base, face, texture)),
+				None => {
+					self.report.unmapped_prints.insert(brick.base.print.clone());
+				}
+			}
+		}
+		let look = self.options.color_fx.get(brick.base.color_fx);
+		for item in items.iter_mut() {
+			look.apply(item);
+		}
+		self.cycles_colors |= look.cycle_colors;
+		let mut shape_script = None;
+		for item in items.iter_mut() {
+			let script = self.options.shape_fx.apply(brick.base.shape_fx, item);
+			shape_script = shape_script.or(script);
+		}
+		if let Some(script) = shape_script {
+			attach_script(&mut items, script);
+		}
+		let extras = Extras::parse(&brick.unknown_extra);
+		if let Some(light) = extras.light {
+			match self.options.lights.get(&light.datablock) {
+				Some(definition) => {
+					if let Some(part) = first_part(&mut items) {
+						part.children
+							.push(definition.to_item(light.enabled, self.options.scale));
+					}
+				}
+				None => {
+					self.report.unknown_lights.insert(light.datablock);
+				}
+			}
+		}
+		if let Some(vehicle) = extras.vehicle {
+			if let Some(part) = first_part(&mut items) {
+				part.set_attribute("BlocklandVehicle", AttributeValue::String(vehicle.datablock));
+			}
+		}
+		if let Some(item) = extras.item {
+			let position = cframe_from_pos_and_rot(brick.base.position, 0, false, self.options.scale)
+				.vector;
+			let bottom = top(&items).unwrap_or_else(|| position.y());
+			items.push(item_placeholder(
+				&item,
+				Vector3::new(position.x(), bottom, position.z()),
+				self.options.scale,
+			));
+		}
+		if !extras.events.is_empty() {
+			let helpers = &self
+				.event_helpers
+				.get_or_insert_with(events::helpers_module)
+				.referent;
+			let script = events::events_script(
+				&extras.events,
+				colors,
+				helpers,
+				&mut self.report.unsupported_events,
+			);
+			if let Some(script) = script {
+				attach_script(&mut items, script);
+			}
+		}
+		if self.options.brick_attributes {
+			group(&mut items);
+			if let Some(item) = items.first_mut() {
+				set_brick_attributes(item, &brick.base, extras.owner, index);
+			}
+		}
+		if let Some(name) = extras.name {
+			group(&mut items);
+			if let Some(item) = items.first_mut() {
+				item.properties
+					.insert("Name", Property::String(name.clone()));
+				item.add_tag(&name);
+				item.set_attribute("BlocklandName", AttributeValue::String(name.clone()));
+				self.named.entry(name).or_default().push(item.referent.clone());
+			}
+		}
+		Ok(ConvertedBrick {
+			owner: extras.owner,
+			ui_name: brick.base.ui_name.clone(),
+			position: brick.base.position,
+			angle: brick.base.angle,
+			color_index: brick.base.color_index,
+			items,
+		})
+	}
+
+	/// Stop events from targeting bricks that were left out
+	fn forget_names(&mut self, bricks: &[ConvertedBrick]) {
+		let referents = bricks
+			.iter()
+			.flat_map(|brick| brick.items.iter().map(|item| &item.referent))
+			.collect::<HashSet<_>>();
+		for named in self.named.values_mut() {
+			named.retain(|referent| !referents.contains(referent));
+		}
+		self.named.retain(|_, named| !named.is_empty());
+	}
+
+	/// Point events at the bricks they target by name. Bricks converted with
+	/// `convert_brick` need this once every brick has been converted, it's
+	/// already done by `convert_bricks`.
+	pub fn resolve_targets(&mut self, items: &mut [Item]) {
+		events::resolve_targets(items, &self.named, &mut self.report.missing_targets);
+	}
+
+	/// The report of everything converted since the last call, which is also
+	/// part of the result of `convert_bricks`
+	pub fn take_report(&mut self) -> Report {
+		std::mem::take(&mut self.report)
+	}
+}
+
+/// A Folder with culled bricks, which can't be seen or touched
+fn hidden_folder(bricks: Vec<ConvertedBrick>) -> Item {
+	fn hide(item: &mut Item) {
+		if item.properties.contains_key("CanCollide") {
+			item.properties.insert("Transparency", Property::Float(1.));
+			item.properties.insert("CanCollide", Property::Bool(false));
+		}
+		for child in item.children.iter_mut() {
+			hide(child);
+		}
+	}
+
+	let mut folder = Item::new("Folder");
+	folder
+		.properties
+		.insert("Name", Property::String("Hidden bricks".to_string()));
+	for mut item in bricks.into_iter().flat_map(|brick| brick.items) {
+		hide(&mut item);
+		folder.children.push(item);
+	}
+	folder
+}
+
+/// Store where a brick came from in the attributes of its item
+fn set_brick_attributes(
+	item: &mut Item,
+	brick: &bl_save::BrickBase,
+	owner: Option<u32>,
+	index: usize,
+) {
+	item.set_attribute("BlocklandUiName", AttributeValue::String(brick.ui_name.clone()));
+	item.set_attribute(
+		"BlocklandColorIndex",
+		AttributeValue::Number(brick.color_index as f64),
+	);
+	if let Some(owner) = owner {
+		item.set_attribute("BlocklandOwner", AttributeValue::Number(owner as f64));
+	}
+	item.set_attribute("BlocklandAngle", AttributeValue::Number(brick.angle as f64));
+	item.set_attribute("BlocklandIsBaseplate", AttributeValue::Bool(brick.is_baseplate));
+	if !brick.print.is_empty() {
+		item.set_attribute("BlocklandPrint", AttributeValue::String(brick.print.clone()));
+	}
+	item.set_attribute("BlocklandIndex", AttributeValue::Number(index as f64));
+}
+
+/// Put the items of a brick made of several items into a Model
+fn group(items: &mut Vec<Item>) {
+	if items.len() > 1 {
+		let mut model = Item::default("Model");
+		model.children = std::mem::take(items);
+		items.push(model);
+	}
+}
+
+/// Add a Script that changes its parent and everything in it to a brick.
+/// Bricks made of several items are grouped into a Model first.
+fn attach_script(items: &mut Vec<Item>, script: Item) {
+	group(items);
+	if let Some(item) = items.first_mut() {
+		item.children.push(script);
+	}
+}
+
+/// The first item that isn't a Model, looking inside of Models
+fn first_part(items: &mut [Item]) -> Option<&mut Item> {
+	let item = items.first_mut()?;
+	if item.class == "Model" {
+		first_part(&mut item.children)
+	} else {
+		Some(item)
+	}
+}
+
+/// The height of the top of the highest part in some items
+fn top(items: &[Item]) -> Option<f32> {
+	items
+		.iter()
+		.filter_map(|item| {
+			let own = match (item.properties.get("CFrame"), item.properties.get("size")) {
+				(Some(Property::CFrame(cframe)), Some(Property::Vector3(size))) => {
+					let half: f32 = (0..3)
+						.map(|col| cframe.rotation[(1, col)].abs() * size.0[col] / 2.)
+						.sum();
+					Some(cframe.vector.y() + half)
+				}
+				_ => None,
+			};
+			own.into_iter().chain(top(&item.children)).reduce(f32::max)
+		})
+		.reduce(f32::max)
+}
+
+/// A Tool standing in for the item a brick spawns, with a small Handle
+/// floating just above `base`. The Handle is anchored so it stays put until
+/// the place gives it to players.
+fn item_placeholder(item: &ItemExtra, base: Vector3, scale: f32) -> Item {
+	let mut handle = Item::default("Part");
+	handle
+		.properties
+		.insert("Name", Property::String("Handle".to_string()));
+	handle
+		.properties
+		.insert("size", Property::Vector3(Vector3::new(1., 1., 1.) * scale));
+	handle.properties.insert(
+		"CFrame",
+		Property::CFrame(CFrame {
+			vector: base + Vector3::new(0., scale, 0.),
+			rotation: nalgebra::Rotation3::identity(),
+		}),
+	);
+	handle.properties.insert("CanCollide", Property::Bool(false));
+
+	let mut tool = Item::new("Tool");
+	tool.properties
+		.insert("Name", Property::String(item.datablock.clone()));
+	tool.set_attribute("BlocklandItem", AttributeValue::String(item.datablock.clone()));
+	tool.set_attribute(
+		"BlocklandRespawnTime",
+		AttributeValue::Number(item.respawn_time as f64 / 1000.),
+	);
+	tool.children.push(handle);
+	tool
+}
+
+/// The face that the print of a print brick goes on, and the part that has it
+fn print_face<'a>(
+	brick: &bl_save::BrickBase,
+	items: &'a mut [Item],
+) -> Option<(NormalId, &'a mut Item)> {
+	if brick.print.is_empty() {
+		return None;
+	}
+	let face = if let Some(caps) = TALL_BRICK_RE.captures(&brick.ui_name) {
+		caps.get(4).map(|_| NormalId::Right)
+	} else if let Some(caps) = REGULAR_BRICK_RE.captures(&brick.ui_name) {
+		// Plates are printed on top, and the rest on the side that faces the
+		// front after turning them
+		caps.get(5).map(|_| {
+			if caps.get(3).is_some() {
+				NormalId::Top
+			} else {
+				NormalId::Right
+			}
+		})
+	} else if let Some(caps) = RAMP_BRICK_RE.captures(&brick.ui_name) {
+		// The slope of a WedgePart is its top face
+		caps.get(4).map(|_| NormalId::Top)
+	} else {
+		None
+	}?;
+	// Bricks from definition files might not be a single part
+	items
+		.first_mut()
+		.filter(|item| item.class != "Model")
+		.map(|item| (face, item))
+}
+
+fn decal(brick: &bl_save::BrickBase, face: NormalId, texture: &str) -> Item {
+	let mut decal = Item::new("Decal");
+	decal
+		.properties
+		.insert("Face", Property::Token(face.token()));
+	decal
+		.properties
+		.insert("Texture", Property::Content(Content(texture.to_string())));
+	decal.properties.insert(
+		"Transparency",
+		Property::Float(if brick.rendering { 0. } else { 1. }),
+	);
+	decal
+}
+
+fn items_from_brick(
+	brick: &bl_save::BrickBase,
+	colors: &[(f32, f32, f32, f32); 64],
+	options: &ConvertOptions,
+	cache: &mut SpecialBricksCache,
+) -> Result<Vec<Item>> {
+	let scale = options.scale;
+
+	/// Scale an item that was built around the origin, then move and rotate it into place
+	fn apply_size_and_cframe(cframe: &CFrame, size: &Vector3, item: &mut Item) {
+		item.properties.entry("size").and_modify(|s| match s {
+			Property::Vector3(v) => *v *= *size,
+			_ => unreachable!(),
+		});
+		item.properties.entry("CFrame").and_modify(|c| match c {
+			Property::CFrame(ci) => {
+				*ci *= *size;
+				*ci = *cframe * *ci;
+			}
+			_ => unreachable!(),
+		});
+		for child in item.children.iter_mut() {
+			apply_size_and_cframe(cframe, size, child);
+		}
+	}
+
+	fn insert_basics(
+		brick: &bl_save::BrickBase,
+		colors: &[(f32, f32, f32, f32); 64],
+		item: &mut Item,
+	) {
+		let color: Color3 = colors[brick.color_index as usize].into();
+		item.properties
+			.entry("Color3uint8")
+			.or_insert(Property::Color3(color));
+		if !item.properties.contains_key("Transparency") || !brick.rendering {
+			item.properties.insert(
+				"Transparency",
+				Property::Float(if !brick.rendering {
+					1.
+				} else {
+					1. - (color.a as f32 / 255.)
+				}),
+			);
+		}
+		item.properties
+			.entry("CanCollide")
+			.or_insert(Property::Bool(brick.collision));
+		for child in item.children.iter_mut() {
+			insert_basics(brick, colors, child);
+		}
+	}
+
+	if brick.color_index as usize >= colors.len() {
+		return Err(Error::InvalidColor {
+			ui_name: brick.ui_name.clone(),
+			color_index: brick.color_index,
+		});
+	}
+
+	if let Some(mut item) = options.definitions.generate(&brick.ui_name) {
+		check_placement(brick)?;
+		let cframe = cframe_from_pos_and_rot(brick.position, brick.angle, false, scale);
+		let size = Vector3::new(1., 1., 1.) * scale;
+		apply_size_and_cframe(&cframe, &size, &mut item);
+		insert_basics(brick, colors, &mut item);
+		return Ok(vec![item]);
+	}
+
+	match get_brick_type(brick, scale)? {
+		BrickType::Regular { cframe, size, mesh } => Ok(vec![{
+			let mut item = Item::default("Part");
+			item.properties.insert("size", Property::Vector3(size));
+			item.properties.insert("CFrame", Property::CFrame(cframe));
+			insert_basics(brick, colors, &mut item);
+			if let RegularBrickMesh::Round = mesh {
+				item.children.push(Item::default("CylinderMesh"))
+			}
+			item
+		}]),
+		BrickType::Ramp {
+			cframe,
+			size,
+			run,
+			lip,
+		} => Ok(specialbricks::ramp_parts(size, run, lip)
+			.into_iter()
+			.map(|mut item| {
+				if let Some(Property::CFrame(part_cframe)) = item.properties.get_mut("CFrame") {
+					*part_cframe = cframe * *part_cframe;
+				}
+				insert_basics(brick, colors, &mut item);
+				item
+			})
+			.collect()),
+		BrickType::RampCorner {
+			wedge_cframe_1,
+			wedge_cframe_2,
+			corner_cframe,
+			size,
+			run,
+			lip,
+			inverted,
+		} => {
+			let up = if inverted { -1. } else { 1. };
+			let wedge_offset = Vector3::new(0., up * lip / 2., 0.);
+			let wedge_height = size.y() - lip;
+			let wall = size.x() - run;
+			let forward = forward_from_angle(brick.angle);
+			let right = right_from_angle(brick.angle);
+			// Offsets from the center to the middle of the slopes and of the
+			// back walls
+			let slope = (size.x() - run) / 2.;
+			let back = -(size.x() - wall) / 2.;
+			Ok(vec![
+				{
+					// Corner wedge of corner ramp
+					let mut item = Item::default("CornerWedgePart");
+					item.properties.insert(
+						"size",
+						Property::Vector3(Vector3::new(run, wedge_height, run)),
+					);
+					item.properties.insert(
+						"CFrame",
+						Property::CFrame(
+							corner_cframe + forward * slope + right * slope + wedge_offset,
+						),
+					);
+					insert_basics(brick, colors, &mut item);
+
+					item
+				},
+				{
+					// Corner of corner ramp
+					let mut item = Item::default("Part");
+					item.properties.insert(
+						"size",
+						Property::Vector3(Vector3::new(wall, wedge_height, wall)),
+					);
+					item.properties.insert(
+						"CFrame",
+						Property::CFrame(
+							corner_cframe + forward * back + right * back + wedge_offset,
+						),
+					);
+					insert_basics(brick, colors, &mut item);
+
+					item
+				},
+				{
+					// First side of the corner ramp
+					let mut item = Item::default("WedgePart");
+					item.properties.insert(
+						"size",
+						Property::Vector3(Vector3::new(wall, wedge_height, run)),
+					);
+					item.properties.insert(
+						"CFrame",
+						Property::CFrame(
+							wedge_cframe_1 + forward * back + right * slope + wedge_offset,
+						),
+					);
+					insert_basics(brick, colors, &mut item);
+					item
+				},
+				{
+					// Second side of the corner ramp
+					let mut item = Item::default("WedgePart");
+					item.properties.insert(
+						"size",
+						Property::Vector3(Vector3::new(wall, wedge_height, run)),
+					);
+					item.properties.insert(
+						"CFrame",
+						Property::CFrame(
+							wedge_cframe_2 + forward * slope + right * back + wedge_offset,
+						),
+					);
+					insert_basics(brick, colors, &mut item);
+					item
+				},
+				{
+					// Lip of corner ramp (bottom of ramp)
+					let mut item = Item::default("Part");
+					item.properties.insert(
+						"size",
+						Property::Vector3(Vector3::new(size.x(), lip, size.z())),
+					);
+					item.properties.insert(
+						"CFrame",
+						Property::CFrame(
+							corner_cframe + Vector3::new(0., up * (lip - size.y()) / 2., 0.),
+						),
+					);
+					insert_basics(brick, colors, &mut item);
+					item
+				},
+			])
+		}
+		BrickType::Unknown => {
+			let cframe = cframe_from_pos_and_rot(brick.position, brick.angle, false, scale);
+			let size = Vector3::new(1., 1., 1.) * scale;
+			match brick.ui_name.as_str() {
+				// Special bricks
+				// TODO: Roads
+				"2x2x2 Cone" => {
+					let mut cone = cache.cone2x2x2();
+
+					apply_size_and_cframe(&cframe, &size, &mut cone);
+					insert_basics(brick, colors, &mut cone);
+					Ok(vec![cone])
+				}
+				"1x1 Cone" => {
+					let mut cone = cache.cone1x1();
+					apply_size_and_cframe(&cframe, &size, &mut cone);
+					insert_basics(brick, colors, &mut cone);
+					Ok(vec![cone])
+				}
+				"Castle Wall" => {
+					let mut wall = cache.castle_wall();
+					apply_size_and_cframe(&cframe, &size, &mut wall);
+					insert_basics(brick, colors, &mut wall);
+					Ok(vec![wall])
+				}
+				"Spawn Point" => {
+					let mut spawn = cache.spawn_point();
+					apply_size_and_cframe(&cframe, &size, &mut spawn);
+					insert_basics(brick, colors, &mut spawn);
+					Ok(vec![spawn])
+				}
+				"1x4x5 Window" => {
+					let mut window = cache.window_1x4x3();
+					apply_size_and_cframe(&cframe, &size, &mut window);
+					insert_basics(brick, colors, &mut window);
+					Ok(vec![window])
+				}
+				"25° Crest Corner" => {
+					let mut crest = cache.crest_corner_25();
+					apply_size_and_cframe(&cframe, &size, &mut crest);
+					insert_basics(brick, colors, &mut crest);
+					Ok(vec![crest])
+				}
+				"25° Crest End" => {
+					let mut crest = cache.crest_end_25();
+					apply_size_and_cframe(&cframe, &size, &mut crest);
+					insert_basics(brick, colors, &mut crest);
+					Ok(vec![crest])
+				}
+				"45° Crest Corner" => {
+					let mut crest = cache.crest_corner_45();
+					apply_size_and_cframe(&cframe, &size, &mut crest);
+					insert_basics(brick, colors, &mut crest);
+					Ok(vec![crest])
+				}
+				"45° Crest End" => {
+					let mut crest = cache.crest_end_45();
+					apply_size_and_cframe(&cframe, &size, &mut crest);
+					insert_basics(brick, colors, &mut crest);
+					Ok(vec![crest])
+				}
+				"Horse Ramp" => {
+					let mut ramp = cache.horse_ramp();
+					apply_size_and_cframe(&cframe, &size, &mut ramp);
+					insert_basics(brick, colors, &mut ramp);
+					Ok(vec![ramp])
+				}
+				"Pine Tree" => {
+					let mut tree = cache.pine_tree();
+					apply_size_and_cframe(&cframe, &size, &mut tree);
+					insert_basics(brick, colors, &mut tree);
+					Ok(vec![tree])
+				}
+				"Music Brick" => {
+					let mut music = cache.music_brick();
+					apply_size_and_cframe(&cframe, &size, &mut music);
+					insert_basics(brick, colors, &mut music);
+					Ok(vec![music])
+				}
+				"Vehicle Spawn" => {
+					let mut spawn = cache.vehicle_spawn();
+					apply_size_and_cframe(&cframe, &size, &mut spawn);
+					insert_basics(brick, colors, &mut spawn);
+					spawn
+						.children
+						.push(specialbricks::vehicle_spawn_attachment(scale));
+					Ok(vec![spawn])
+				}
+				"Checkpoint" => {
+					let mut checkpoint = cache.checkpoint();
+					apply_size_and_cframe(&cframe, &size, &mut checkpoint);
+					insert_basics(brick, colors, &mut checkpoint);
+					Ok(vec![checkpoint])
+				}
+				"2x2 Disc" => {
+					let mut disc = cache.disc_2x2();
+					apply_size_and_cframe(&cframe, &size, &mut disc);
+					insert_basics(brick, colors, &mut disc);
+					Ok(vec![disc])
+				}
+				_ => {
+					if let Some(caps) = CREST_BRICK_RE.captures(&brick.ui_name) {
+						let height = caps.get(1).unwrap().as_str();
+						let height = if height == "25" { 2. / 3. } else { 1. };
+						let length = caps.get(2).unwrap().as_str().parse::<u8>().unwrap();
+						let mut crest = specialbricks::generate_crest(height, length);
+						apply_size_and_cframe(&cframe, &size, &mut crest);
+						insert_basics(brick, colors, &mut crest);
+						Ok(vec![crest])
+					} else if let Some((length, height)) = ARCH_BRICK_RE
+						.captures(&brick.ui_name)
+						.and_then(|caps| caps.get(1).unwrap().as_str().parse().ok())
+						.and_then(|length| Some((length, specialbricks::arch_height(length)?)))
+					{
+						let mut arch = specialbricks::generate_arch(length, height);
+						apply_size_and_cframe(&cframe, &size, &mut arch);
+						insert_basics(brick, colors, &mut arch);
+						Ok(vec![arch])
+					} else if let Some(caps) = POLE_BRICK_RE.captures(&brick.ui_name) {
+						let height = match (caps.get(1), caps.get(2)) {
+							(_, Some(height)) => height.as_str().parse::<f32>().unwrap(),
+							(Some(_), None) => 1. / 3.,
+							(None, None) => 1.,
+						};
+						if height == 0. {
+							return Err(invalid_geometry(brick, "size can't be zero"));
+						}
+						let mut pole = specialbricks::generate_pole(height);
+						apply_size_and_cframe(&cframe, &size, &mut pole);
+						insert_basics(brick, colors, &mut pole);
+						Ok(vec![pole])
+					} else if let Some(caps) = TERRAIN_BRICK_RE.captures(&brick.ui_name) {
+						let width = caps.get(1).unwrap().as_str().parse::<f32>().unwrap();
+						if width == 0. {
+							return Err(invalid_geometry(brick, "size can't be zero"));
+						}
+						let shape =
+							specialbricks::TerrainShape::from_name(caps.get(2).unwrap().as_str())
+								.unwrap();
+						let steep = caps.get(3).is_some();
+						let mut terrain = specialbricks::generate_terrain(shape, width, steep);
+						apply_size_and_cframe(&cframe, &size, &mut terrain);
+						insert_basics(brick, colors, &mut terrain);
+						Ok(vec![terrain])
+					} else if let Some(mut item) = options.blb_bricks.generate(&brick.ui_name) {
+						apply_size_and_cframe(&cframe, &size, &mut item);
+						insert_basics(brick, colors, &mut item);
+						Ok(vec![item])
+					} else {
+						Err(Error::UnsupportedBrick(brick.ui_name.clone()))
+					}
+				}
+			}
+		}
+	}
+}
+
+fn invalid_geometry(brick: &bl_save::BrickBase, reason: &str) -> Error {
+	Error::InvalidGeometry {
+		ui_name: brick.ui_name.clone(),
+		reason: reason.to_string(),
+	}
+}
+
+/// Make sure the rotation and position of a brick can be converted
+fn check_placement(brick: &bl_save::BrickBase) -> Result<()> {
+	if brick.angle > 3 {
+		return Err(invalid_geometry(brick, &format!("angle {} is not 0-3", brick.angle)));
+	}
+	let (x, y, z) = brick.position;
+	if !(x.is_finite() && y.is_finite() && z.is_finite()) {
+		return Err(invalid_geometry(brick, "position is not a number"));
+	}
+	Ok(())
+}
+
+pub fn get_brick_type(brick: &bl_save::BrickBase, scale: f32) -> Result<BrickType> {
+	check_placement(brick)?;
+	let invalid_geometry = |reason: &str| invalid_geometry(brick, reason);
+
+	let brick_type = if let Some(caps) = TALL_BRICK_RE.captures(&brick.ui_name) {
+		let x: f32 = caps.get(1).unwrap().as_str().parse().unwrap();
+		let z: f32 = caps.get(2).unwrap().as_str().parse().unwrap();
+		let y = caps.get(3).unwrap().as_str().parse::<f32>().unwrap() * BRICK_HEIGHT;
+		if x == 0. || y == 0. || z == 0. {
+			return Err(invalid_geometry("size can't be zero"));
+		}
+		BrickType::Regular {
+			size: Vector3::new(x, y, z) * scale,
+			cframe: cframe_from_pos_and_rot(
+				brick.position,
+				(brick.angle + if caps.get(4).is_some() { 1 } else { 0 }) % 4,
+				false,
+				scale,
+			),
+			mesh: RegularBrickMesh::Block,
+		}
+	} else if let Some(caps) = REGULAR_BRICK_RE.captures(&brick.ui_name) {
+		let x: f32 = caps.get(1).unwrap().as_str().parse().unwrap(); // These will never panic, check the RE
+		let z: f32 = caps.get(2).unwrap().as_str().parse().unwrap();
+		let y = if caps.get(3).is_some() {
+			0.4
+		} else {
+			BRICK_HEIGHT
+		};
+		if x == 0. || z == 0. {
+			return Err(invalid_geometry("size can't be zero"));
+		}
+		BrickType::Regular {
+			size: Vector3::new(x, y, z) * scale,
+			cframe: cframe_from_pos_and_rot(
+				brick.position,
+				(brick.angle + if caps.get(5).is_some() { 1 } else { 0 }) % 4,
+				false,
+				scale,
+			),
+			mesh: if caps.get(4).is_some() {
+				RegularBrickMesh::Round
+			} else {
+				RegularBrickMesh::Block
+			},
+		}
+	} else if let Some(caps) = CUBE_BRICK_RE.captures(&brick.ui_name) {
+		// As tall as they are wide, so an 8x Cube is 8 studs on every side. The
+		// steep cubes of terrain packs are twice as tall.
+		let x: f32 = caps.get(1).unwrap().as_str().parse().unwrap();
+		if x == 0. {
+			return Err(invalid_geometry("size can't be zero"));
+		}
+		let y = if caps.get(2).is_some() { 2. * x } else { x };
+		BrickType::Regular {
+			size: Vector3::new(x, y, x) * scale,
+			cframe: cframe_from_pos_and_rot(brick.position, brick.angle, false, scale),
+			mesh: RegularBrickMesh::Block,
+		}
+	} else if let Some(caps) = RAMP_BRICK_RE.captures(&brick.ui_name) {
+		let angle = parse_ramp_angle(caps.get(2).unwrap().as_str())
+			.ok_or_else(|| Error::UnsupportedBrick(brick.ui_name.clone()))?;
+
+		let x = match caps.get(3).unwrap().as_str().parse::<u8>() {
+			Ok(x) if x > 0 => x,
+			_ => return Err(invalid_geometry("ramp width must be 1-255")),
+		};
+		let inverted = caps.get(1).is_some();
+		BrickType::Ramp {
+			size: Vector3::new(x as f32, angle.height(), angle.depth()) * scale,
+			run: angle.run() * scale,
+			lip: angle.lip() * scale,
+			cframe: cframe_from_pos_and_rot(brick.position, brick.angle, inverted, scale),
+		}
+	} else if let Some(caps) = CORNER_RAMP_BRICK_RE.captures(&brick.ui_name) {
+		let angle = parse_ramp_angle(caps.get(2).unwrap().as_str())
+			.ok_or_else(|| Error::UnsupportedBrick(brick.ui_name.clone()))?;
+
+		// The slopes on both sides meet a one stud square corner
+		let x = angle.depth();
+		let inverted = caps.get(1).is_some();
+		BrickType::RampCorner {
+			size: Vector3::new(x, angle.height(), x) * scale,
+			run: angle.run() * scale,
+			lip: angle.lip() * scale,
+			corner_cframe: cframe_from_pos_and_rot(
+				brick.position,
+				(brick.angle + if inverted { 3 } else { 2 }) % 4,
+				inverted,
+				scale,
+			),
+			wedge_cframe_1: cframe_from_pos_and_rot(
+				brick.position,
+				(brick.angle + 1) % 4,
+				inverted,
+				scale,
+			),
+			wedge_cframe_2: cframe_from_pos_and_rot(brick.position, brick.angle, inverted, scale),
+			inverted,
+		}
+	} else {
+		BrickType::Unknown
+	};
+	Ok(brick_type)
+}
+
+fn forward_from_angle(angle: u8) -> Vector3 {
+	match angle {
+		0 => Vector3::new(0., 0., -1.),
+		1 => Vector3::new(1., 0., 0.),
+		2 => Vector3::new(0., 0., 1.),
+		_ => Vector3::new(-1., 0., 0.),
+	}
+}
+
+fn right_from_angle(angle: u8) -> Vector3 {
+	match angle {
+		0 => Vector3::new(1., 0., 0.),
+		1 => Vector3::new(0., 0., 1.),
+		2 => Vector3::new(-1., 0., 0.),
+		_ => Vector3::new(0., 0., -1.),
+	}
+}
+
+pub fn cframe_from_pos_and_rot(pos: (f32, f32, f32), angle: u8, inverted: bool, scale: f32) -> CFrame {
+	let x = pos.0 * 2. * scale;
+	let y = pos.2 * 2. * scale;
+	let z = -pos.1 * 2. * scale;
+	let mut rot = nalgebra::Rotation3::new(nalgebra::Vector3::new(
+		0.,
+		-(angle as f32 * <f32 as nalgebra::RealField>::frac_pi_2()),
+		0.,
+	));
+	rot *= nalgebra::Rotation3::new(nalgebra::Vector3::new(
+		0.,
+		0.,
+		if inverted {
+			<f32 as nalgebra::RealField>::pi()
+		} else {
+			0.
+		},
+	));
+	CFrame {
+		vector: Vector3::new(x, y, z),
+		rotation: rot,
+	}
+}
+
+/// The slopes of ramps, with the sizes of the stock .blb files. A ramp is a
+/// slope in front of a one stud thick back wall, and the slope sits on a lip.
+/// 25° ramps are three studs deep and rise one brick, 45° ramps are two studs
+/// deep and rise one brick. The steep 72° and 80° ramps are two studs deep and
+/// rise three and five bricks from a lip one plate thick, which keeps their
+/// slopes at 72.6° and 79.9°.
+pub enum RampAngle {
+	Angle25,
+	Angle45,
+	Angle72,
+	Angle80,
+}
+
+impl RampAngle {
+	/// Height of the ramp in studs
+	pub fn height(&self) -> f32 {
+		BRICK_HEIGHT
+			* match self {
+				RampAngle::Angle25 | RampAngle::Angle45 => 1.,
+				RampAngle::Angle72 => 3.,
+				RampAngle::Angle80 => 5.,
+			}
+	}
+
+	/// How many studs deep the ramp is, back wall included. Corners are as
+	/// deep on both sides.
+	pub fn depth(&self) -> f32 {
+		match self {
+			RampAngle::Angle25 => 3.,
+			RampAngle::Angle45 | RampAngle::Angle72 | RampAngle::Angle80 => 2.,
+		}
+	}
+
+	/// How many studs deep the slope is, not counting the back wall
+	pub fn run(&self) -> f32 {
+		self.depth() - 1.
+	}
+
+	/// Height of the lip under the slope in studs
+	pub fn lip(&self) -> f32 {
+		match self {
+			RampAngle::Angle25 | RampAngle::Angle45 => WEDGE_LIP_SIZE * BRICK_HEIGHT,
+			RampAngle::Angle72 | RampAngle::Angle80 => BRICK_HEIGHT / 3.,
+		}
+	}
+}
+
+pub fn parse_ramp_angle(s: &str) -> Option<RampAngle> {
+	match s {
+		"25" => Some(RampAngle::Angle25),
+		"45" => Some(RampAngle::Angle45),
+		"72" => Some(RampAngle::Angle72),
+		"80" => Some(RampAngle::Angle80),
+		_ => None,
+	}
+}
+
+pub enum RegularBrickMesh {
+	Block,
+	Round,
+}
+
+pub enum BrickType {
+	Regular {
+		cframe: CFrame,
+		size: Vector3,
+		mesh: RegularBrickMesh,
+	},
+	/// `size` is the whole brick, the slope is `run` deep and sits on a lip
+	/// `lip` high. Inverted ramps are turned upside down by `cframe`.
+	Ramp {
+		cframe: CFrame,
+		size: Vector3,
+		run: f32,
+		lip: f32,
+	},
+	RampCorner {
+		corner_cframe: CFrame,
+		wedge_cframe_1: CFrame,
+		wedge_cframe_2: CFrame,
+		size: Vector3,
+		run: f32,
+		lip: f32,
+		inverted: bool,
+	},
+	Unknown,
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn brick(ui_name: &str, position: (f32, f32, f32), angle: u8) -> bl_save::BrickBase {
+		bl_save::BrickBase {
+			ui_name: ui_name.to_string(),
+			position,
+			angle,
+			is_baseplate: false,
+			color_index: 0,
+			print: String::new(),
+			color_fx: 0,
+			shape_fx: 0,
+			raycasting: true,
+			collision: true,
+			rendering: true,
+		}
+	}
+
+	fn assert_vector(actual: Vector3, expected: (f32, f32, f32)) {
+		let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+		assert!(
+			close(actual.x(), expected.0) && close(actual.y(), expected.1) && close(actual.z(), expected.2),
+			"expected {:?}, got ({}, {}, {})",
+			expected,
+			actual.x(),
+			actual.y(),
+			actual.z()
+		);
+	}
+
+	fn regular(brick: &bl_save::BrickBase, scale: f32) -> (Vector3, CFrame) {
+		match get_brick_type(brick, scale).unwrap() {
+			BrickType::Regular {
+				size,
+				cframe,
+				mesh: RegularBrickMesh::Block,
+			} => (size, cframe),
+			_ => panic!("{} is not a regular block brick", brick.ui_name),
+		}
+	}
+
+	#[test]
+	fn cube_sizes() {
+		for &(ui_name, side) in &[
+			("4x Cube", 4.),
+			("8x Cube", 8.),
+			("16x Cube", 16.),
+			("32x Cube", 32.),
+			("64x Cube", 64.),
+		] {
+			let (size, cframe) = regular(&brick(ui_name, (0., 0., side / 4.), 0), 1.);
+			assert_vector(size, (side, side, side));
+			// Sitting on the ground
+			assert_vector(cframe.vector, (0., side / 2., 0.));
+		}
+	}
+
+	#[test]
+	fn cube_positions() {
+		let (size, cframe) = regular(&brick("8x Cube", (10., -3.5, 2.), 1), 1.);
+		assert_vector(size, (8., 8., 8.));
+		assert_vector(cframe.vector, (20., 4., 7.));
+
+		let (size, cframe) = regular(&brick("32x Cube", (1., 2., 8.), 2), 0.5);
+		assert_vector(size, (16., 16., 16.));
+		assert_vector(cframe.vector, (1., 8., -2.));
+	}
+
+	/// Class, size and position of a part
+	type PartPlacement = (&'static str, Vector3, Vector3);
+
+	/// Class, size and position of a part, as they should be
+	type ExpectedPart<'a> = (&'a str, (f32, f32, f32), (f32, f32, f32));
+
+	/// The items a brick is converted to with the default options
+	fn items(ui_name: &str, position: (f32, f32, f32), angle: u8) -> Vec<Item> {
+		let colors = [(0.5, 0.5, 0.5, 1.); 64];
+		items_from_brick(
+			&brick(ui_name, position, angle),
+			&colors,
+			&ConvertOptions::default(),
+			&mut SpecialBricksCache::new(),
+		)
+		.unwrap()
+	}
+
+	/// Every part a brick is converted to
+	fn parts(ui_name: &str, position: (f32, f32, f32), angle: u8) -> Vec<PartPlacement> {
+		fn add_parts(items: &[Item], parts: &mut Vec<PartPlacement>) {
+			for item in items {
+				if let (Some(Property::Vector3(size)), Some(Property::CFrame(cframe))) =
+					(item.properties.get("size"), item.properties.get("CFrame"))
+				{
+					parts.push((item.class, *size, cframe.vector));
+				}
+				add_parts(&item.children, parts);
+			}
+		}
+
+		let items = items(ui_name, position, angle);
+		let mut parts = vec![];
+		add_parts(&items, &mut parts);
+		parts
+	}
+
+	fn assert_parts(ui_name: &str, position: (f32, f32, f32), angle: u8, expected: &[ExpectedPart]) {
+		let actual = parts(ui_name, position, angle);
+		assert_eq!(actual.len(), expected.len(), "number of parts of {}", ui_name);
+		for ((class, size, position), (expected_class, expected_size, expected_position)) in
+			actual.into_iter().zip(expected)
+		{
+			assert_eq!(class, *expected_class, "class of a part of {}", ui_name);
+			assert_vector(size, *expected_size);
+			assert_vector(position, *expected_position);
+		}
+	}
+
+	/// The class of a wedge and which way its slope faces
+	type Slope = (&'static str, (f32, f32, f32));
+
+	/// Which way the slope of every wedge of a brick faces. For a WedgePart
+	/// that's the normal of its sloped face, which rises towards +Z before it
+	/// is rotated. For a CornerWedgePart it's the corner its peak is in, which
+	/// is at +X and -Z before it is rotated.
+	fn slopes(ui_name: &str, angle: u8) -> Vec<Slope> {
+		fn add_slopes(items: &[Item], slopes: &mut Vec<Slope>) {
+			for item in items {
+				if let (Some(Property::Vector3(size)), Some(Property::CFrame(cframe))) =
+					(item.properties.get("size"), item.properties.get("CFrame"))
+				{
+					let local = match item.class {
+						"WedgePart" => nalgebra::Vector3::new(0., size.z(), -size.y()).normalize(),
+						"CornerWedgePart" => nalgebra::Vector3::new(1., 1., -1.),
+						_ => continue,
+					};
+					let world = cframe.rotation * local;
+					slopes.push((item.class, (world.x, world.y, world.z)));
+				}
+				add_slopes(&item.children, slopes);
+			}
+		}
+
+		let items = items(ui_name, (0., 0., 0.), angle);
+		let mut slopes = vec![];
+		add_slopes(&items, &mut slopes);
+		slopes
+	}
+
+	fn assert_slopes(ui_name: &str, angle: u8, expected: &[Slope]) {
+		let actual = slopes(ui_name, angle);
+		assert_eq!(actual.len(), expected.len(), "number of wedges of {}", ui_name);
+		for ((class, normal), (expected_class, expected_normal)) in actual.into_iter().zip(expected) {
+			assert_eq!(class, *expected_class, "class of a wedge of {}", ui_name);
+			assert_vector(Vector3::new(normal.0, normal.1, normal.2), *expected_normal);
+		}
+	}
+
+	const FRONT: (f32, f32) = (0., -1.);
+	const BACK: (f32, f32) = (0., 1.);
+	const RIGHT: (f32, f32) = (1., 0.);
+
+	/// The normal of the slope of a Blockland ramp, which rises `rise` studs
+	/// above the lip over `run` studs, facing `facing` on the ground and up or
+	/// down
+	fn slope(rise: f32, run: f32, facing: (f32, f32), up: bool) -> (f32, f32, f32) {
+		let length = (rise * rise + run * run).sqrt();
+		let y = run / length;
+		(
+			facing.0 * rise / length,
+			if up { y } else { -y },
+			facing.1 * rise / length,
+		)
+	}
+
+	#[test]
+	fn ramps() {
+		// The lip is 0.15 studs thick, a plate on steep ramps, and the slope
+		// starts above it
+		assert_parts(
+			"25° Ramp 2x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (2., 1.05, 2.), (0., 0.075, -0.5)),
+				("Part", (2., 0.15, 2.), (0., -0.525, -0.5)),
+				("Part", (2., 1.2, 1.), (0., 0., 1.)),
+			],
+		);
+		assert_parts(
+			"45° Ramp 1x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (1., 1.05, 1.), (0., 0.075, -0.5)),
+				("Part", (1., 0.15, 1.), (0., -0.525, -0.5)),
+				("Part", (1., 1.2, 1.), (0., 0., 0.5)),
+			],
+		);
+		assert_parts(
+			"72° Ramp 4x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (4., 3.2, 1.), (0., 0.2, -0.5)),
+				("Part", (4., 0.4, 1.), (0., -1.6, -0.5)),
+				("Part", (4., 3.6, 1.), (0., 0., 0.5)),
+			],
+		);
+		assert_parts(
+			"80° Ramp 2x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (2., 5.6, 1.), (0., 0.2, -0.5)),
+				("Part", (2., 0.4, 1.), (0., -2.8, -0.5)),
+				("Part", (2., 6., 1.), (0., 0., 0.5)),
+			],
+		);
+
+		// The slopes face the front, rising the height of the ramp above the
+		// lip over its length
+		assert_slopes("25° Ramp 2x", 0, &[("WedgePart", slope(1.05, 2., FRONT, true))]);
+		assert_slopes("45° Ramp 1x", 0, &[("WedgePart", slope(1.05, 1., FRONT, true))]);
+		assert_slopes("72° Ramp 4x", 0, &[("WedgePart", slope(3.2, 1., FRONT, true))]);
+		assert_slopes("80° Ramp 2x", 0, &[("WedgePart", slope(5.6, 1., FRONT, true))]);
+	}
+
+	#[test]
+	fn inverted_ramps() {
+		// Upside down, with the lip at the top
+		assert_parts(
+			"-25° Ramp 1x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (1., 1.05, 2.), (0., -0.075, -0.5)),
+				("Part", (1., 0.15, 2.), (0., 0.525, -0.5)),
+				("Part", (1., 1.2, 1.), (0., 0., 1.)),
+			],
+		);
+		assert_parts(
+			"-45° Ramp 2x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (2., 1.05, 1.), (0., -0.075, -0.5)),
+				("Part", (2., 0.15, 1.), (0., 0.525, -0.5)),
+				("Part", (2., 1.2, 1.), (0., 0., 0.5)),
+			],
+		);
+		assert_parts(
+			"-72° Ramp 1x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (1., 3.2, 1.), (0., -0.2, -0.5)),
+				("Part", (1., 0.4, 1.), (0., 1.6, -0.5)),
+				("Part", (1., 3.6, 1.), (0., 0., 0.5)),
+			],
+		);
+		assert_parts(
+			"-80° Ramp 1x",
+			(0., 0., 0.),
+			0,
+			&[
+				("WedgePart", (1., 5.6, 1.), (0., -0.2, -0.5)),
+				("Part", (1., 0.4, 1.), (0., 2.8, -0.5)),
+				("Part", (1., 6., 1.), (0., 0., 0.5)),
+			],
+		);
+
+		assert_slopes("-25° Ramp 1x", 0, &[("WedgePart", slope(1.05, 2., FRONT, false))]);
+		assert_slopes("-45° Ramp 2x", 0, &[("WedgePart", slope(1.05, 1., FRONT, false))]);
+		assert_slopes("-72° Ramp 1x", 0, &[("WedgePart", slope(3.2, 1., FRONT, false))]);
+		assert_slopes("-80° Ramp 1x", 0, &[("WedgePart", slope(5.6, 1., FRONT, false))]);
+	}
+
+	#[test]
+	fn rotated_ramps() {
+		// Facing +X, at (2, 0.6, -4) in Roblox
+		assert_parts(
+			"45° Ramp 1x",
+			(1., 2., 0.3),
+			1,
+			&[
+				("WedgePart", (1., 1.05, 1.), (2.5, 0.675, -4.)),
+				("Part", (1., 0.15, 1.), (2.5, 0.075, -4.)),
+				("Part", (1., 1.2, 1.), (1.5, 0.6, -4.)),
+			],
+		);
+		assert_parts(
+			"72° Ramp 1x",
+			(0., 0., 0.9),
+			2,
+			&[
+				("WedgePart", (1., 3.2, 1.), (0., 2., 0.5)),
+				("Part", (1., 0.4, 1.), (0., 0.2, 0.5)),
+				("Part", (1., 3.6, 1.), (0., 1.8, -0.5)),
+			],
+		);
+
+		assert_slopes("45° Ramp 1x", 1, &[("WedgePart", slope(1.05, 1., RIGHT, true))]);
+		assert_slopes("72° Ramp 1x", 2, &[("WedgePart", slope(3.2, 1., BACK, true))]);
+	}
+
+	#[test]
+	fn rotated_castle_walls() {
+		// One stud thick and three wide, with window posts at either end
+		let facing = parts("Castle Wall", (0., 0., 0.), 0);
+		let size = bounds("Castle Wall", 0);
+		assert_vector(Vector3::new(size.x(), 0., size.z()), (1., 0., 3.));
+		let posts = facing
+			.iter()
+			.filter(|(_, size, _)| size.z() == 1.)
+			.map(|(_, _, position)| position.z())
+			.collect::<Vec<_>>();
+		assert_eq!(posts, [1., -1.]);
+
+		// Every part turns around the center of the brick, a quarter to the
+		// right for every step of the angle
+		for angle in 0..4 {
+			let turn = cframe_from_pos_and_rot((0., 0., 0.), angle, false, 1.);
+			let turned = parts("Castle Wall", (0., 0., 0.), angle);
+			assert_eq!(turned.len(), facing.len());
+			for ((class, size, position), (facing_class, facing_size, facing_position)) in
+				turned.into_iter().zip(facing.iter())
+			{
+				let expected = turn.point_to_world_space(*facing_position);
+				assert_eq!(class, *facing_class);
+				assert_vector(size, (facing_size.x(), facing_size.y(), facing_size.z()));
+				assert_vector(position, (expected.x(), expected.y(), expected.z()));
+			}
+			let expected = if angle % 2 == 0 { (1., size.y(), 3.) } else { (3., size.y(), 1.) };
+			assert_vector(bounds("Castle Wall", angle), expected);
+		}
+
+		// Facing +X the posts are to the left and right
+		let turned = parts("Castle Wall", (0., 0., 0.), 1);
+		let posts = turned
+			.iter()
+			.filter(|(_, size, _)| size.z() == 1.)
+			.map(|(_, _, position)| position.x())
+			.collect::<Vec<_>>();
+		assert_eq!(posts, [-1., 1.]);
+	}
+
+	#[test]
+	fn ramp_corners() {
+		assert_parts(
+			"45° Ramp Corner",
+			(0., 0., 0.),
+			0,
+			&[
+				("CornerWedgePart", (1., 1.05, 1.), (0.5, 0.075, -0.5)),
+				("Part", (1., 1.05, 1.), (-0.5, 0.075, 0.5)),
+				("WedgePart", (1., 1.05, 1.), (0.5, 0.075, 0.5)),
+				("WedgePart", (1., 1.05, 1.), (-0.5, 0.075, -0.5)),
+				("Part", (2., 0.15, 2.), (0., -0.525, 0.)),
+			],
+		);
+		assert_parts(
+			"25° Ramp Corner",
+			(0., 0., 0.),
+			0,
+			&[
+				("CornerWedgePart", (2., 1.05, 2.), (0.5, 0.075, -0.5)),
+				("Part", (1., 1.05, 1.), (-1., 0.075, 1.)),
+				("WedgePart", (1., 1.05, 2.), (0.5, 0.075, 1.)),
+				("WedgePart", (1., 1.05, 2.), (-1., 0.075, -0.5)),
+				("Part", (3., 0.15, 3.), (0., -0.525, 0.)),
+			],
+		);
+		assert_parts(
+			"-80° Ramp Corner",
+			(0., 0., 0.),
+			0,
+			&[
+				("CornerWedgePart", (1., 5.6, 1.), (0.5, -0.2, -0.5)),
+				("Part", (1., 5.6, 1.), (-0.5, -0.2, 0.5)),
+				("WedgePart", (1., 5.6, 1.), (0.5, -0.2, 0.5)),
+				("WedgePart", (1., 5.6, 1.), (-0.5, -0.2, -0.5)),
+				("Part", (2., 0.4, 2.), (0., 2.8, 0.)),
+			],
+		);
+
+		// The corner peaks towards the full height block in the back left, and
+		// the wedges on either side of it rise towards it
+		assert_slopes(
+			"45° Ramp Corner",
+			0,
+			&[
+				("CornerWedgePart", (-1., 1., 1.)),
+				("WedgePart", slope(1.05, 1., RIGHT, true)),
+				("WedgePart", slope(1.05, 1., FRONT, true)),
+			],
+		);
+		assert_slopes(
+			"25° Ramp Corner",
+			0,
+			&[
+				("CornerWedgePart", (-1., 1., 1.)),
+				("WedgePart", slope(1.05, 2., RIGHT, true)),
+				("WedgePart", slope(1.05, 2., FRONT, true)),
+			],
+		);
+		assert_slopes(
+			"-80° Ramp Corner",
+			0,
+			&[
+				("CornerWedgePart", (-1., -1., 1.)),
+				("WedgePart", slope(5.6, 1., RIGHT, false)),
+				("WedgePart", slope(5.6, 1., FRONT, false)),
+			],
+		);
+		// Turned a quarter to the right, the block is in the front left
+		assert_slopes(
+			"25° Ramp Corner",
+			1,
+			&[
+				("CornerWedgePart", (-1., 1., -1.)),
+				("WedgePart", slope(1.05, 2., BACK, true)),
+				("WedgePart", slope(1.05, 2., RIGHT, true)),
+			],
+		);
+	}
+
+	/// The size of the box around every part of a brick
+	fn bounds(ui_name: &str, angle: u8) -> Vector3 {
+		type Point = nalgebra::Vector3<f32>;
+		fn add_bounds(items: &[Item], min: &mut Point, max: &mut Point) {
+			for item in items {
+				if let (Some(Property::Vector3(size)), Some(Property::CFrame(cframe))) =
+					(item.properties.get("size"), item.properties.get("CFrame"))
+				{
+					let half = cframe.rotation.matrix().abs() * (size.0 / 2.);
+					*min = min.zip_map(&(cframe.vector.0 - half), f32::min);
+					*max = max.zip_map(&(cframe.vector.0 + half), f32::max);
+				}
+				add_bounds(&item.children, min, max);
+			}
+		}
+
+		let mut min = nalgebra::Vector3::repeat(f32::INFINITY);
+		let mut max = nalgebra::Vector3::repeat(f32::NEG_INFINITY);
+		add_bounds(&items(ui_name, (0., 0., 0.), angle), &mut min, &mut max);
+		Vector3(max - min)
+	}
+
+	#[test]
+	fn ramps_fill_their_blb_size() {
+		// Width, height and depth in studs of the stock .blb files
+		let sizes = [
+			("25° Ramp 1x", (1., 1.2, 3.)),
+			("25° Ramp 4x", (4., 1.2, 3.)),
+			("-25° Ramp 2x", (2., 1.2, 3.)),
+			("45° Ramp 2x", (2., 1.2, 2.)),
+			("-45° Ramp 1x", (1., 1.2, 2.)),
+			("72° Ramp 1x", (1., 3.6, 2.)),
+			("72° Ramp 4x", (4., 3.6, 2.)),
+			("-72° Ramp 1x", (1., 3.6, 2.)),
+			("80° Ramp 2x", (2., 6., 2.)),
+			("-80° Ramp 1x", (1., 6., 2.)),
+			("25° Ramp Corner", (3., 1.2, 3.)),
+			("45° Ramp Corner", (2., 1.2, 2.)),
+			("72° Ramp Corner", (2., 3.6, 2.)),
+			("-72° Ramp Corner", (2., 3.6, 2.)),
+			("80° Ramp Corner", (2., 6., 2.)),
+		];
+		for (ui_name, size) in sizes.iter() {
+			assert_vector(bounds(ui_name, 0), *size);
+			// Turned a quarter, width and depth swap
+			assert_vector(bounds(ui_name, 1), (size.2, size.1, size.0));
+		}
+	}
+
+	#[test]
+	fn steep_ramps_slope_at_their_angle() {
+		let angles = [("72° Ramp 1x", 72.), ("80° Ramp 1x", 80.), ("-72° Ramp 1x", 72.)];
+		for (ui_name, degrees) in angles.iter() {
+			let (_, normal) = slopes(ui_name, 0)[0];
+			let slope = normal.1.abs().acos().to_degrees();
+			assert!((slope - degrees).abs() < 1., "{} slopes at {}°", ui_name, slope);
+		}
+	}
+
+	/// The bricks that come with Blockland, which should all be converted
+	const DEFAULT_BRICKS: &[&str] = &[
+		// Bricks
+		"1x1", "1x2", "1x3", "1x4", "1x6", "1x8", "1x10", "1x12", "1x16", "2x2", "2x3", "2x4",
+		"2x6", "2x8", "2x10", "2x12", "4x4", "4x6", "4x8",
+		// Tall bricks
+		"1x1x2", "1x1x3", "1x1x5", "1x2x2", "1x2x3", "1x2x5", "1x4x5", "1x6x5", "2x2x5",
+		// Plates
+		"1x1F", "1x2F", "1x3F", "1x4F", "1x6F", "1x8F", "1x10F", "1x12F", "2x2F", "2x3F",
+		"2x4F", "2x6F", "2x8F", "2x10F", "2x12F", "4x4F", "4x6F", "4x8F", "4x12F", "4x16F",
+		"6x6F", "6x8F", "6x12F", "6x16F", "8x8F", "8x12F", "8x16F", "16x16F", "32x32F",
+		// Baseplates
+		"16x16 Base", "32x32 Base", "48x48 Base", "64x64 Base",
+		// Round bricks
+		"1x1 Round", "1x1F Round", "2x2 Round", "2x2F Round", "4x4F Round", "2x2 Disc",
+		// Prints
+		"1x1 Print", "1x1F Print", "1x2F Print", "1x4x4 Print", "2x2F Print",
+		"45° Ramp 1x Print",
+		// Ramps
+		"25° Ramp 1x", "25° Ramp 2x", "25° Ramp 4x", "45° Ramp 1x", "45° Ramp 2x",
+		"45° Ramp 4x", "72° Ramp 1x", "72° Ramp 2x", "72° Ramp 4x", "80° Ramp 1x",
+		"80° Ramp 2x", "80° Ramp 4x", "-25° Ramp 1x", "-25° Ramp 2x", "-45° Ramp 1x",
+		"-45° Ramp 2x", "-72° Ramp 1x", "-80° Ramp 1x", "25° Ramp Corner",
+		"45° Ramp Corner", "72° Ramp Corner", "80° Ramp Corner", "-25° Ramp Corner",
+		"-45° Ramp Corner", "-72° Ramp Corner", "-80° Ramp Corner",
+		// Crests
+		"25° Crest 1x", "25° Crest 2x", "25° Crest 4x", "25° Crest Corner", "25° Crest End",
+		"45° Crest 1x", "45° Crest 2x", "45° Crest 4x", "45° Crest Corner", "45° Crest End",
+		// Cubes
+		"4x Cube", "8x Cube", "16x Cube", "32x Cube", "64x Cube",
+		// Arches and poles
+		"1x3 Arch", "1x4 Arch", "1x6 Arch", "1x8 Arch", "1x1 Pole", "1x1F Pole",
+		"1x1x3 Pole", "1x1x5 Pole",
+		// Special bricks
+		"1x1 Cone", "2x2x2 Cone", "Castle Wall", "1x4x5 Window", "Spawn Point",
+		"Horse Ramp", "Pine Tree", "Music Brick", "Vehicle Spawn", "Checkpoint",
+	];
+
+	#[test]
+	fn default_bricks() {
+		let colors = [(0.5, 0.5, 0.5, 1.); 64];
+		let options = ConvertOptions::default();
+		let mut cache = SpecialBricksCache::new();
+		let unsupported = DEFAULT_BRICKS
+			.iter()
+			.filter(|ui_name| {
+				let brick = brick(ui_name, (0., 0., 0.), 0);
+				!items_from_brick(&brick, &colors, &options, &mut cache)
+					.is_ok_and(|items| !items.is_empty())
+			})
+			.collect::<Vec<_>>();
+		assert!(unsupported.is_empty(), "not converted: {:?}", unsupported);
+	}
+
+	/// Convert a brick with extra lines, like `+-ITEM`, with the default options
+	fn convert_with_extras(base: bl_save::BrickBase, extras: &[&str]) -> Vec<Item> {
+		let colors = [(0.5, 0.5, 0.5, 1.); 64];
+		let brick = bl_save::Brick {
+			base,
+			unknown_extra: extras.iter().map(|line| line.to_string()).collect(),
+		};
+		Converter::new(ConvertOptions::default())
+			.convert_brick(&brick, &colors)
+			.unwrap()
+	}
+
+	#[test]
+	fn lights() {
+		let colors = [(0.5, 0.5, 0.5, 1.); 64];
+		let mut converter = Converter::new(ConvertOptions::default());
+		let lit = bl_save::Brick {
+			base: brick("2x2", (0., 0., 0.3), 0),
+			unknown_extra: vec!["+-LIGHT Red\" 1".to_string()],
+		};
+		let items = converter.convert_brick(&lit, &colors).unwrap();
+		let light = &items[0].children[0];
+		assert_eq!(light.class, "PointLight");
+		assert!(matches!(light.properties.get("Range"), Some(Property::Float(r)) if *r == 30.));
+
+		// Unknown datablocks are reported, and their bricks have no light
+		let unknown = bl_save::Brick {
+			base: brick("2x2", (0., 0., 0.3), 0),
+			unknown_extra: vec!["+-LIGHT Disco Light\" 1".to_string()],
+		};
+		let items = converter.convert_brick(&unknown, &colors).unwrap();
+		assert!(items[0].children.iter().all(|child| !child.class.ends_with("Light")));
+		let report = converter.take_report();
+		assert_eq!(report.unknown_lights.into_iter().collect::<Vec<_>>(), ["Disco Light"]);
+	}
+
+	/// The face and texture of the decal on a brick with `print`
+	fn print_decal(converter: &mut Converter, ui_name: &str, print: &str) -> Option<(u32, String)> {
+		let colors = [(0.5, 0.5, 0.5, 1.); 64];
+		let mut base = brick(ui_name, (0., 0., 0.3), 0);
+		base.print = print.to_string();
+		let printed = bl_save::Brick {
+			base,
+			unknown_extra: vec![],
+		};
+		let items = converter.convert_brick(&printed, &colors).unwrap();
+		let decal = items[0].children.iter().find(|child| child.class == "Decal")?;
+		match (decal.properties.get("Face"), decal.properties.get("Texture")) {
+			(Some(Property::Token(face)), Some(Property::Content(Content(texture)))) => {
+				Some((*face, texture.clone()))
+			}
+			_ => panic!("decal has no face or texture"),
+		}
+	}
+
+	#[test]
+	fn prints() {
+		let mut options = ConvertOptions::default();
+		options.prints.add_toml("\"Letters/A\" = 123", "test").unwrap();
+		let mut converter = Converter::new(options);
+		let texture = "rbxassetid://123".to_string();
+
+		// Plates are printed on top, other bricks on their front, and ramps on
+		// their slope
+		let top = Some((NormalId::Top.token(), texture.clone()));
+		let side = Some((NormalId::Right.token(), texture.clone()));
+		assert_eq!(print_decal(&mut converter, "1x2F Print", "Letters/A"), top);
+		assert_eq!(print_decal(&mut converter, "1x2 Print", "Letters/A"), side);
+		assert_eq!(print_decal(&mut converter, "1x4x4 Print", "Letters/A"), side);
+		assert_eq!(print_decal(&mut converter, "45° Ramp 2x Print", "Letters/A"), top);
+		// Bricks that aren't print bricks keep no print
+		assert_eq!(print_decal(&mut converter, "1x2", "Letters/A"), None);
+		assert!(converter.take_report().unmapped_prints.is_empty());
+
+		// Prints without a texture are reported instead
+		assert_eq!(print_decal(&mut converter, "1x2 Print", "Letters/B"), None);
+		assert_eq!(
+			converter.take_report().unmapped_prints.into_iter().collect::<Vec<_>>(),
+			["Letters/B"]
+		);
+	}
+
+	fn string_attribute<'a>(item: &'a Item, name: &str) -> Option<&'a str> {
+		match item.properties.get("AttributesSerialize") {
+			Some(Property::Attributes(attributes)) => match attributes.0.get(name) {
+				Some(AttributeValue::String(value)) => Some(value),
+				_ => None,
+			},
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn checkpoints() {
+		// A 4x4 plate that players spawn on top of
+		assert_parts(
+			"Checkpoint",
+			(2., -1., 0.1),
+			1,
+			&[("SpawnLocation", (4., 0.4, 4.), (4., 0.2, 2.))],
+		);
+	}
+
+	#[test]
+	fn vehicle_spawns() {
+		assert_parts(
+			"Vehicle Spawn",
+			(0., 0., 0.1),
+			0,
+			&[("Part", (8., 0.4, 8.), (0., 0.2, 0.))],
+		);
+
+		let items = convert_with_extras(brick("Vehicle Spawn", (0., 0., 0.1), 0), &["+-VEHICLE Jeep\" 1"]);
+		assert_eq!(items.len(), 1);
+		let spawn = &items[0];
+		assert_eq!(string_attribute(spawn, "BlocklandVehicle"), Some("Jeep"));
+		assert!(matches!(
+			spawn.properties.get("Tags"),
+			Some(Property::Tags(tags)) if tags.0.iter().any(|tag| tag == "VehicleSpawn")
+		));
+		// On top of the plate, relative to it
+		let attachment = &spawn.children[0];
+		assert_eq!(attachment.class, "Attachment");
+		match attachment.properties.get("CFrame") {
+			Some(Property::CFrame(cframe)) => assert_vector(cframe.vector, (0., 0.2, 0.)),
+			_ => panic!("attachment has no CFrame"),
+		}
+	}
+
+	#[test]
+	fn item_placeholders() {
+		let items = convert_with_extras(brick("2x2", (1., 0., 0.3), 0), &["+-ITEM Gun\" 0 2 4000"]);
+		assert_eq!(items.len(), 2);
+		let tool = &items[1];
+		assert_eq!(tool.class, "Tool");
+		assert_eq!(string_attribute(tool, "BlocklandItem"), Some("Gun"));
+		// The handle floats a stud above the top of the brick
+		let handle = &tool.children[0];
+		match handle.properties.get("CFrame") {
+			Some(Property::CFrame(cframe)) => assert_vector(cframe.vector, (2., 2.2, 0.)),
+			_ => panic!("handle has no CFrame"),
+		}
+	}
+
+	#[test]
+	fn steep_cubes() {
+		let (size, cframe) = regular(&brick("8x Cube Steep", (0., 0., 4.), 0), 1.);
+		assert_vector(size, (8., 16., 8.));
+		assert_vector(cframe.vector, (0., 8., 0.));
+	}
+
+	#[test]
+	fn terrain_bricks() {
+		assert_parts(
+			"4x Wedge",
+			(1., 2., 1.),
+			0,
+			&[("WedgePart", (4., 4., 4.), (2., 2., -4.))],
+		);
+		assert_slopes("4x Wedge", 0, &[("WedgePart", slope(4., 4., FRONT, true))]);
+
+		// The slope is over the front half
+		assert_parts(
+			"8x Ramp",
+			(0., 0., 2.),
+			0,
+			&[
+				("WedgePart", (8., 8., 4.), (0., 4., -2.)),
+				("Part", (8., 8., 4.), (0., 4., 2.)),
+			],
+		);
+		assert_slopes("8x Ramp", 1, &[("WedgePart", slope(8., 4., RIGHT, true))]);
+
+		// Peaking in the back right
+		assert_parts(
+			"4x Corner A",
+			(0., 0., 1.),
+			0,
+			&[("CornerWedgePart", (4., 4., 4.), (0., 2., 0.))],
+		);
+		assert_slopes("4x Corner A", 0, &[("CornerWedgePart", (1., 1., 1.))]);
+
+		// Two wedges rising to the back and the right, twice as tall as wide
+		assert_parts(
+			"4x Corner B Steep",
+			(0., 0., 2.),
+			0,
+			&[
+				("WedgePart", (4., 8., 4.), (0., 4., 0.)),
+				("WedgePart", (4., 8., 4.), (0., 4., 0.)),
+			],
+		);
+		assert_slopes(
+			"4x Corner B Steep",
+			0,
+			&[
+				("WedgePart", slope(8., 4., FRONT, true)),
+				("WedgePart", slope(8., 4., (-1., 0.), true)),
+			],
+		);
+	}
+
+	#[test]
+	fn upside_down_terrain_corners() {
+		assert_parts(
+			"4x Corner C",
+			(0., 0., 1.),
+			0,
+			&[("CornerWedgePart", (4., 4., 4.), (0., 2., 0.))],
+		);
+		assert_slopes("4x Corner C", 0, &[("CornerWedgePart", (-1., -1., 1.))]);
+
+		assert_parts(
+			"8x CornerD",
+			(0., 0., 2.),
+			0,
+			&[
+				("WedgePart", (8., 8., 8.), (0., 4., 0.)),
+				("WedgePart", (8., 8., 8.), (0., 4., 0.)),
+			],
+		);
+		assert_slopes(
+			"8x CornerD",
+			0,
+			&[
+				("WedgePart", slope(8., 8., FRONT, false)),
+				("WedgePart", slope(8., 8., RIGHT, false)),
+			],
+		);
+	}
+
+	#[test]
+	fn zero_sized_cube_is_invalid() {
+		assert!(matches!(
+			get_brick_type(&brick("0x Cube", (0., 0., 0.), 0), 1.),
+			Err(Error::InvalidGeometry { .. })
+		));
+	}
+}
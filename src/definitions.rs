@@ -0,0 +1,456 @@
+//! Brick definitions loaded from TOML files, so add-on bricks can be converted
+//! without recompiling.
+//!
+//! A definition file is a list of `[[brick]]` tables. Each brick is matched by
+//! its exact UI `name` or a regular expression `pattern`, and is made out of one
+//! or more `[[brick.part]]`s:
+//!
+//! ```toml
+//! [[brick]]
+//! pattern = '^(\d+)x(\d+) Glass$'
+//!
+//! [[brick.part]]
+//! class = "Part"                  # defaults to Part
+//! size = ["$1", 0.4, "$2"]        # studs, plates are 0.4 and bricks 1.2 tall
+//! offset = [0, 0, 0]              # from the center of the brick
+//! rotation = [0, 0, 0]            # degrees around X, Y and Z
+//! properties = { Material = { token = 1568 }, Reflectance = { float = 0.2 } }
+//! ```
+//!
+//! Sizes and offsets can use the groups captured by `pattern`: `"$1"` is the
+//! first group, and `"$3 * 1.2"` is the third group multiplied by 1.2.
+//! Properties can be `bool`, `float`, `token`, `int`, `string`, `vector3` or
+//! `color3` (red, green and blue from 0 to 255). The brick's color,
+//! transparency and collision are only applied to properties a part doesn't
+//! set itself.
+//!
+//! A part's class is `Part`, `WedgePart`, `CornerWedgePart`, `TrussPart`,
+//! `Seat` or `SpawnLocation`. Unknown keys are an error, so typos don't go
+//! unnoticed.
+
+use crate::error::{Error, Result};
+use crate::types::{CFrame, Color3, Item, Property, Vector3};
+
+use nalgebra::Rotation3;
+use regex::Regex;
+use serde::Deserialize;
+
+use std::{collections::HashMap, fs, path::Path};
+
+/// Classes that take the part properties every brick is given
+const PART_CLASSES: &[&str] = &[
+    "Part",
+    "WedgePart",
+    "CornerWedgePart",
+    "TrussPart",
+    "Seat",
+    "SpawnLocation",
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DefinitionFile {
+    #[serde(default)]
+    brick: Vec<RawBrick>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBrick {
+    name: Option<String>,
+    pattern: Option<String>,
+    #[serde(default)]
+    part: Vec<RawPart>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPart {
+    #[serde(default = "default_class")]
+    class: String,
+    size: [RawDimension; 3],
+    #[serde(default)]
+    offset: [RawDimension; 3],
+    #[serde(default)]
+    rotation: [f32; 3],
+    #[serde(default)]
+    properties: HashMap<String, RawProperty>,
+}
+
+fn default_class() -> String {
+    "Part".to_string()
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDimension {
+    Number(f32),
+    Capture(String),
+}
+
+impl Default for RawDimension {
+    fn default() -> Self {
+        RawDimension::Number(0.)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RawProperty {
+    Bool(bool),
+    Float(f32),
+    Token(u32),
+    Int(i64),
+    String(String),
+    Vector3([f32; 3]),
+    Color3([u8; 3]),
+}
+
+impl From<RawProperty> for Property {
+    fn from(raw: RawProperty) -> Property {
+        match raw {
+            RawProperty::Bool(v) => Property::Bool(v),
+            RawProperty::Float(v) => Property::Float(v),
+            RawProperty::Token(v) => Property::Token(v),
+            RawProperty::Int(v) => Property::Int(v),
+            RawProperty::String(v) => Property::String(v),
+            RawProperty::Vector3([x, y, z]) => Property::Vector3(Vector3::new(x, y, z)),
+            RawProperty::Color3([r, g, b]) => Property::Color3(Color3 { r, g, b, a: 255 }),
+        }
+    }
+}
+
+/// A length that is either fixed or taken from a group of the name pattern
+#[derive(Clone, Copy)]
+struct Dimension {
+    capture: Option<usize>,
+    factor: f32,
+}
+
+impl Dimension {
+    fn parse(raw: &RawDimension) -> std::result::Result<Dimension, String> {
+        let s = match raw {
+            RawDimension::Number(n) => {
+                return Ok(Dimension {
+                    capture: None,
+                    factor: *n,
+                })
+            }
+            RawDimension::Capture(s) => s,
+        };
+        let invalid = || format!("{:?} is not a number or a capture like \"$1\"", s);
+        let mut split = s.splitn(2, '*');
+        let group = split
+            .next()
+            .and_then(|group| group.trim().strip_prefix('$'))
+            .and_then(|group| group.parse().ok())
+            .ok_or_else(invalid)?;
+        let factor = match split.next() {
+            Some(factor) => factor.trim().parse().map_err(|_| invalid())?,
+            None => 1.,
+        };
+        Ok(Dimension {
+            capture: Some(group),
+            factor,
+        })
+    }
+
+    fn resolve(&self, captures: &[f32]) -> Option<f32> {
+        match self.capture {
+            Some(group) => captures.get(group).map(|v| v * self.factor),
+            None => Some(self.factor),
+        }
+    }
+}
+
+enum NameMatcher {
+    Exact(String),
+    Pattern(Regex),
+}
+
+struct PartDefinition {
+    class: &'static str,
+    size: [Dimension; 3],
+    offset: [Dimension; 3],
+    rotation: Rotation3<f32>,
+    properties: Vec<(&'static str, Property)>,
+}
+
+struct BrickDefinition {
+    matcher: NameMatcher,
+    parts: Vec<PartDefinition>,
+}
+
+/// Bricks described by definition files
+#[derive(Default)]
+pub struct BrickDefinitions {
+    definitions: Vec<BrickDefinition>,
+}
+
+/// Property names live as long as the program, like the ones written in the
+/// source
+fn intern(s: String) -> &'static str {
+    Box::leak(s.into_boxed_str())
+}
+
+impl BrickDefinitions {
+    pub fn new() -> Self {
+        BrickDefinitions::default()
+    }
+
+    /// Load a definition file, or every `.toml` file in a directory
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        if path.is_dir() {
+            let mut files = fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            files.retain(|file| file.extension().is_some_and(|ext| ext == "toml"));
+            files.sort();
+            for file in files {
+                self.load(&file)?;
+            }
+            Ok(())
+        } else {
+            let source = fs::read_to_string(path)?;
+            self.add_toml(&source, &path.display().to_string())
+        }
+    }
+
+    /// Add the definitions in a TOML document. `origin` is used in error
+    /// messages. Definitions added later take precedence over earlier ones.
+    pub fn add_toml(&mut self, source: &str, origin: &str) -> Result<()> {
+        let error = |reason: String| Error::Definition {
+            origin: origin.to_string(),
+            reason,
+        };
+        let file: DefinitionFile = toml::from_str(source).map_err(|e| error(e.to_string()))?;
+        for raw in file.brick {
+            let matcher = match (raw.name, raw.pattern) {
+                (Some(name), None) => NameMatcher::Exact(name),
+                (None, Some(pattern)) => {
+                    NameMatcher::Pattern(Regex::new(&pattern).map_err(|e| error(e.to_string()))?)
+                }
+                _ => {
+                    return Err(error(
+                        "every brick needs either a name or a pattern".to_string(),
+                    ))
+                }
+            };
+            if raw.part.is_empty() {
+                return Err(error("every brick needs at least one part".to_string()));
+            }
+            let mut parts = Vec::with_capacity(raw.part.len());
+            for part in raw.part {
+                let class = match PART_CLASSES.iter().find(|class| **class == part.class) {
+                    Some(class) => *class,
+                    None => return Err(error(format!("{:?} is not a part class", part.class))),
+                };
+                let dimensions = |raw: &[RawDimension; 3]| -> Result<[Dimension; 3]> {
+                    Ok([
+                        Dimension::parse(&raw[0]).map_err(error)?,
+                        Dimension::parse(&raw[1]).map_err(error)?,
+                        Dimension::parse(&raw[2]).map_err(error)?,
+                    ])
+                };
+                let [rx, ry, rz] = part.rotation;
+                parts.push(PartDefinition {
+                    class,
+                    size: dimensions(&part.size)?,
+                    offset: dimensions(&part.offset)?,
+                    rotation: Rotation3::from_euler_angles(
+                        rx.to_radians(),
+                        ry.to_radians(),
+                        rz.to_radians(),
+                    ),
+                    properties: part
+                        .properties
+                        .into_iter()
+                        .map(|(name, value)| (intern(name), value.into()))
+                        .collect(),
+                });
+            }
+            self.definitions.push(BrickDefinition { matcher, parts });
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    /// Build the brick with this UI name around the origin, with sizes and
+    /// offsets in studs as the definition gives them. The converter scales
+    /// and places it like any other brick. Returns `None` if no definition
+    /// matches, or if the captures used by the definition aren't numbers.
+    pub fn generate(&self, ui_name: &str) -> Option<Item> {
+        let (definition, captures) =
+            self.definitions
+                .iter()
+                .rev()
+                .find_map(|definition| match &definition.matcher {
+                    NameMatcher::Exact(name) if name == ui_name => Some((definition, vec![])),
+                    NameMatcher::Pattern(re) => re.captures(ui_name).map(|caps| {
+                        let captures = caps
+                            .iter()
+                            .map(|cap| {
+                                cap.and_then(|cap| cap.as_str().parse().ok())
+                                    .unwrap_or(f32::NAN)
+                            })
+                            .collect();
+                        (definition, captures)
+                    }),
+                    _ => None,
+                })?;
+
+        let mut parts = Vec::with_capacity(definition.parts.len());
+        for part in definition.parts.iter() {
+            let resolve = |dimensions: &[Dimension; 3]| {
+                let x = dimensions[0].resolve(&captures)?;
+                let y = dimensions[1].resolve(&captures)?;
+                let z = dimensions[2].resolve(&captures)?;
+                if x.is_finite() && y.is_finite() && z.is_finite() {
+                    Some(Vector3::new(x, y, z))
+                } else {
+                    None
+                }
+            };
+            let mut item = Item::default(part.class);
+            item.properties
+                .insert("size", Property::Vector3(resolve(&part.size)?));
+            item.properties.insert(
+                "CFrame",
+                Property::CFrame(CFrame {
+                    vector: resolve(&part.offset)?,
+                    rotation: part.rotation,
+                }),
+            );
+            for (name, value) in part.properties.iter() {
+                item.properties.insert(name, value.clone());
+            }
+            parts.push(item);
+        }
+
+        if parts.len() == 1 {
+            parts.pop()
+        } else {
+            let mut model = Item::default("Model");
+            model.children = parts;
+            Some(model)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions(source: &str) -> Result<BrickDefinitions> {
+        let mut definitions = BrickDefinitions::new();
+        definitions.add_toml(source, "test.toml")?;
+        Ok(definitions)
+    }
+
+    fn error_reason(source: &str) -> String {
+        match definitions(source) {
+            Err(Error::Definition { origin, reason }) => {
+                assert_eq!(origin, "test.toml");
+                reason
+            }
+            Err(e) => panic!("expected a definition error, got {}", e),
+            Ok(_) => panic!("expected a definition error"),
+        }
+    }
+
+    fn vector(item: &Item, name: &str) -> [f32; 3] {
+        match item.properties.get(name) {
+            Some(Property::Vector3(v)) => [v.0.x, v.0.y, v.0.z],
+            _ => panic!("{} is not a Vector3", name),
+        }
+    }
+
+    #[test]
+    fn captures_are_multiplied() {
+        let definitions = definitions(
+            r#"
+            [[brick]]
+            pattern = '^(\d+)x(\d+)x(\d+) Glass$'
+
+            [[brick.part]]
+            class = "WedgePart"
+            size = ["$1", "$3 * 1.2", "$2"]
+            offset = [0, "$3*0.5", 0]
+            properties = { Reflectance = { float = 0.2 } }
+            "#,
+        )
+        .unwrap();
+
+        let part = definitions.generate("2x4x5 Glass").unwrap();
+        assert_eq!(part.class, "WedgePart");
+        assert_eq!(vector(&part, "size"), [2., 6., 4.]);
+        match part.properties.get("CFrame") {
+            Some(Property::CFrame(cframe)) => assert_eq!(cframe.vector.0.y, 2.5),
+            _ => panic!("CFrame is not set"),
+        }
+        assert!(matches!(
+            part.properties.get("Reflectance"),
+            Some(Property::Float(r)) if *r == 0.2
+        ));
+        assert!(definitions.generate("2x4 Glass").is_none());
+    }
+
+    #[test]
+    fn bad_expressions_are_errors() {
+        for expression in &["$1 + 1", "$x", "1.2 * $1", "$1 * two"] {
+            let source = format!(
+                "[[brick]]\nname = \"Glass\"\n[[brick.part]]\nsize = [{:?}, 1, 1]\n",
+                expression
+            );
+            let reason = error_reason(&source);
+            assert!(reason.contains(expression), "{}", reason);
+        }
+    }
+
+    #[test]
+    fn unknown_classes_are_errors() {
+        let reason = error_reason(
+            r#"
+            [[brick]]
+            name = "Light"
+
+            [[brick.part]]
+            class = "PointLight"
+            size = [1, 1, 1]
+            "#,
+        );
+        assert!(reason.contains("PointLight"), "{}", reason);
+    }
+
+    #[test]
+    fn unknown_properties_are_errors() {
+        // A typo in the part table
+        let reason = error_reason(
+            r#"
+            [[brick]]
+            name = "Glass"
+
+            [[brick.part]]
+            size = [1, 1, 1]
+            colour = [255, 0, 0]
+            "#,
+        );
+        assert!(reason.contains("colour"), "{}", reason);
+
+        // A property value of a type definitions don't support
+        let reason = error_reason(
+            r#"
+            [[brick]]
+            name = "Glass"
+
+            [[brick.part]]
+            size = [1, 1, 1]
+            properties = { Color = { color3float = [1, 0, 0] } }
+            "#,
+        );
+        assert!(reason.contains("color3float"), "{}", reason);
+    }
+}
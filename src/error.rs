@@ -0,0 +1,73 @@
+use std::{error, fmt, io};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can go wrong while converting a save
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// A line of the save file could not be parsed
+    Parse { line: usize, source: io::Error },
+    /// The brick isn't one of the types that can be converted
+    UnsupportedBrick(String),
+    /// The brick is a known type but its size, position or rotation is invalid
+    InvalidGeometry { ui_name: String, reason: String },
+    /// The brick uses a color that isn't part of the colorset
+    InvalidColor { ui_name: String, color_index: u8 },
+    /// A brick definition or mapping file could not be loaded
+    Definition { origin: String, reason: String },
+}
+
+impl Error {
+    /// Whether the conversion can keep going after skipping the brick that
+    /// caused this error
+    pub fn is_recoverable(&self) -> bool {
+        match self {
+            Error::Io(_) | Error::Definition { .. } => false,
+            Error::Parse { .. }
+            | Error::UnsupportedBrick(_)
+            | Error::InvalidGeometry { .. }
+            | Error::InvalidColor { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Parse { line, source } => write!(f, "Line {}: {}", line, source),
+            Error::UnsupportedBrick(ui_name) => write!(f, "Unknown brick type: {}", ui_name),
+            Error::InvalidGeometry { ui_name, reason } => {
+                write!(f, "Invalid {} brick: {}", ui_name, reason)
+            }
+            Error::InvalidColor {
+                ui_name,
+                color_index,
+            } => write!(
+                f,
+                "Invalid {} brick: color index {} is not in the colorset",
+                ui_name, color_index
+            ),
+            Error::Definition { origin, reason } => {
+                write!(f, "Could not load {}: {}", origin, reason)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) | Error::Parse { source: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
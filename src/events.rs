@@ -0,0 +1,650 @@
+//! Blockland events turned into Luau scripts.
+//!
+//! Every brick with events gets a Script. Events are enabled and disabled
+//! through `EventEnabled<index>` attributes on the brick, so that events on
+//! other bricks can toggle them. Bricks targeted by name are found through
+//! the ObjectValues in the script's `Targets` folder, which point at every
+//! brick with that name once `resolve_targets` has run. The functions the
+//! scripts call live in one shared ModuleScript, which each script finds
+//! through its `Helpers` ObjectValue.
+
+use crate::extras::{EventExtra, EventTarget};
+use crate::types::{Color3, Item, Property, ProtectedString, RbxUuid, Ref};
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+
+/// Input events that can be converted
+const INPUTS: &[&str] = &["onActivate", "onPlayerTouch"];
+
+/// Seconds before touching a brick fires its events again for the same player.
+/// `Touched` fires over and over while a player stands on a brick, so this
+/// stands in for Blockland's limit on how often touch events fire.
+const TOUCH_COOLDOWN: f32 = 0.5;
+
+/// Output events that can be converted
+const OUTPUTS: &[&str] = &[
+    "setColliding",
+    "setRendering",
+    "setColor",
+    "setEventEnabled",
+    "toggleEventEnabled",
+];
+
+/// The name of the ModuleScript with the helpers every events script uses
+pub const HELPERS_NAME: &str = "BlocklandEvents";
+
+// Shared by every events script, so that saves with lots of event bricks don't
+// have a copy of these for every brick
+const HELPERS_SOURCE: &str = r#"-- Helpers shared by the Blockland events of every brick
+local Events = {}
+
+function Events.getParts(instance)
+	local parts = {}
+	if instance:IsA("BasePart") then
+		table.insert(parts, instance)
+	end
+	for _, descendant in ipairs(instance:GetDescendants()) do
+		if descendant:IsA("BasePart") then
+			table.insert(parts, descendant)
+		end
+	end
+	return parts
+end
+
+-- The bricks called `name`, from the ObjectValues in `targets`
+function Events.getNamed(targets, name)
+	local found = {}
+	for _, target in ipairs(targets:GetChildren()) do
+		if target.Name == name and target.Value then
+			table.insert(found, target.Value)
+		end
+	end
+	return found
+end
+
+-- The transparency of a part while it's rendered
+local function getTransparency(part)
+	local transparency = part:GetAttribute("RenderedTransparency")
+	if transparency == nil then
+		transparency = part.Transparency
+		part:SetAttribute("RenderedTransparency", transparency)
+	end
+	return transparency
+end
+
+function Events.setColliding(instance, colliding)
+	for _, part in ipairs(Events.getParts(instance)) do
+		part.CanCollide = colliding
+	end
+end
+
+function Events.setRendering(instance, rendering)
+	for _, part in ipairs(Events.getParts(instance)) do
+		local transparency = getTransparency(part)
+		part.Transparency = if rendering then transparency else 1
+	end
+end
+
+function Events.setColor(instance, color, transparency)
+	for _, part in ipairs(Events.getParts(instance)) do
+		local rendered = part.Transparency == getTransparency(part)
+		part.Color = color
+		part:SetAttribute("RenderedTransparency", transparency)
+		if rendered then
+			part.Transparency = transparency
+		end
+	end
+end
+
+function Events.setEventEnabled(instance, events, enabled)
+	for _, index in ipairs(events) do
+		instance:SetAttribute("EventEnabled" .. index, enabled)
+	end
+end
+
+function Events.toggleEventEnabled(instance, events)
+	for _, index in ipairs(events) do
+		local name = "EventEnabled" .. index
+		instance:SetAttribute(name, not instance:GetAttribute(name))
+	end
+end
+
+return Events
+"#;
+
+/// The ModuleScript with the helpers of events scripts. A place only needs
+/// one of these, which every events script points at.
+pub fn helpers_module() -> Item {
+    let mut module = Item::new("ModuleScript");
+    module
+        .properties
+        .insert("Name", Property::String(HELPERS_NAME.to_string()));
+    module.properties.insert(
+        "Source",
+        Property::ProtectedString(ProtectedString(HELPERS_SOURCE.to_string())),
+    );
+    module
+}
+
+/// Quote a string for Luau
+fn lua_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{{{:x}}}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A list of event numbers, such as `0 1 2`, as a Luau table
+fn lua_event_list(param: Option<&String>) -> String {
+    let events = param
+        .map(|p| {
+            p.split_whitespace()
+                .filter_map(|i| i.parse::<usize>().ok())
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    format!("{{{}}}", events.join(", "))
+}
+
+fn lua_bool(param: Option<&String>) -> &'static str {
+    match param.map(String::as_str) {
+        Some("0") | None => "false",
+        Some(_) => "true",
+    }
+}
+
+/// The Luau call for an output event, or `None` if it isn't supported
+fn output_call(event: &EventExtra, colors: &[(f32, f32, f32, f32); 64]) -> Option<String> {
+    let param = |i: usize| event.params.get(i);
+    Some(match event.output.as_str() {
+        "setColliding" => format!("Events.setColliding(target, {})", lua_bool(param(0))),
+        "setRendering" => format!("Events.setRendering(target, {})", lua_bool(param(0))),
+        "setColor" => {
+            let index = param(0)?.parse::<usize>().ok()?;
+            let color: Color3 = (*colors.get(index)?).into();
+            format!(
+                "Events.setColor(target, Color3.fromRGB({}, {}, {}), {})",
+                color.r,
+                color.g,
+                color.b,
+                1. - color.a as f32 / 255.
+            )
+        }
+        "setEventEnabled" => format!(
+            "Events.setEventEnabled(target, {}, {})",
+            lua_event_list(param(0)),
+            lua_bool(param(1))
+        ),
+        "toggleEventEnabled" => {
+            format!(
+                "Events.toggleEventEnabled(target, {})",
+                lua_event_list(param(0))
+            )
+        }
+        _ => return None,
+    })
+}
+
+/// The Script for the events of a brick, or `None` if none of them can be
+/// converted. `helpers` is the referent of the `helpers_module` the script
+/// uses. The parts of events that can't be converted are added to
+/// `unsupported`.
+pub fn events_script(
+    events: &[EventExtra],
+    colors: &[(f32, f32, f32, f32); 64],
+    helpers: &RbxUuid,
+    unsupported: &mut HashSet<String>,
+) -> Option<Item> {
+    let mut source = String::from(
+        r#"-- Blockland events of this brick
+local Players = game:GetService("Players")
+local Events = require(script:WaitForChild("Helpers").Value)
+
+local brick = script.Parent
+local targets = script:WaitForChild("Targets")
+"#,
+    );
+
+    let mut names = BTreeSet::new();
+    let mut handlers: Vec<(&str, usize)> = vec![];
+    for event in events {
+        let mut supported = true;
+        if !INPUTS.contains(&event.input.as_str()) {
+            unsupported.insert(event.input.clone());
+            supported = false;
+        }
+        let call = output_call(event, colors);
+        if call.is_none() {
+            let output = if OUTPUTS.contains(&event.output.as_str()) {
+                format!("{} {}", event.output, event.params.join(" "))
+            } else {
+                event.output.clone()
+            };
+            unsupported.insert(output);
+            supported = false;
+        }
+        let targets = match &event.target {
+            EventTarget::SelfBrick => "{ brick }".to_string(),
+            EventTarget::Named(name) => {
+                names.insert(name.clone());
+                format!("Events.getNamed(targets, {})", lua_string(name))
+            }
+            EventTarget::Other(target) => {
+                unsupported.insert(format!("{} target", target));
+                supported = false;
+                String::new()
+            }
+        };
+        let call = match call {
+            Some(call) if supported => call,
+            _ => continue,
+        };
+
+        let _ = write!(
+            source,
+            "
+brick:SetAttribute(\"EventEnabled{index}\", {enabled})
+local function event{index}(player)
+	if not brick:GetAttribute(\"EventEnabled{index}\") then
+		return
+	end
+	task.delay({delay}, function()
+		for _, target in ipairs({targets}) do
+			{call}
+		end
+	end)
+end
+",
+            index = event.index,
+            enabled = event.enabled,
+            delay = event.delay as f32 / 1000.,
+            targets = targets,
+            call = call,
+        );
+        handlers.push((event.input.as_str(), event.index));
+    }
+
+    if handlers.is_empty() {
+        return None;
+    }
+
+    let calls = |input: &str| {
+        handlers
+            .iter()
+            .filter(|(i, _)| *i == input)
+            .map(|(_, index)| format!("event{}(player)", index))
+            .collect::<Vec<_>>()
+    };
+    let activate = calls("onActivate");
+    if !activate.is_empty() {
+        let _ = write!(
+            source,
+            "
+local clickDetector = Instance.new(\"ClickDetector\")
+clickDetector.Parent = brick
+clickDetector.MouseClick:Connect(function(player)
+	{}
+end)
+",
+            activate.join("\n\t")
+        );
+    }
+    let touch = calls("onPlayerTouch");
+    if !touch.is_empty() {
+        let _ = write!(
+            source,
+            "
+local lastTouched = {{}}
+Players.PlayerRemoving:Connect(function(player)
+	lastTouched[player] = nil
+end)
+for _, part in ipairs(Events.getParts(brick)) do
+	part.Touched:Connect(function(hit)
+		local player = Players:GetPlayerFromCharacter(hit.Parent)
+		if not player then
+			return
+		end
+		local now = os.clock()
+		if lastTouched[player] and now - lastTouched[player] < {cooldown} then
+			return
+		end
+		lastTouched[player] = now
+		{calls}
+	end)
+end
+",
+            cooldown = TOUCH_COOLDOWN,
+            calls = touch.join("\n\t\t")
+        );
+    }
+
+    let mut script = Item::new("Script");
+    script
+        .properties
+        .insert("Name", Property::String("Events".to_string()));
+    script
+        .properties
+        .insert("Source", Property::ProtectedString(ProtectedString(source)));
+
+    let mut folder = Item::new("Folder");
+    folder
+        .properties
+        .insert("Name", Property::String("Targets".to_string()));
+    for name in names {
+        folder.children.push(object_value(name, None));
+    }
+    script.children.push(folder);
+    script
+        .children
+        .push(object_value("Helpers".to_string(), Some(helpers.clone())));
+    Some(script)
+}
+
+fn object_value(name: String, value: Option<RbxUuid>) -> Item {
+    let mut item = Item::new("ObjectValue");
+    item.properties.insert("Name", Property::String(name));
+    item.properties.insert("Value", Property::Ref(Ref(value)));
+    item
+}
+
+/// Point the `Targets` of every events script in `items` at the bricks with
+/// that name. `named` has the referents of the bricks with each name. Names
+/// that no brick has are added to `missing`.
+pub fn resolve_targets(
+    items: &mut [Item],
+    named: &HashMap<String, Vec<RbxUuid>>,
+    missing: &mut HashSet<String>,
+) {
+    for item in items.iter_mut() {
+        if item.class == "Script" {
+            for folder in item.children.iter_mut() {
+                if folder.class == "Folder" {
+                    resolve_folder(folder, named, missing);
+                }
+            }
+        }
+        resolve_targets(&mut item.children, named, missing);
+    }
+}
+
+fn resolve_folder(
+    folder: &mut Item,
+    named: &HashMap<String, Vec<RbxUuid>>,
+    missing: &mut HashSet<String>,
+) {
+    let mut resolved = Vec::with_capacity(folder.children.len());
+    for target in folder.children.drain(..) {
+        let name = match (
+            target.properties.get("Name"),
+            target.properties.get("Value"),
+        ) {
+            (Some(Property::String(name)), Some(Property::Ref(Ref(None))))
+                if target.class == "ObjectValue" =>
+            {
+                name.clone()
+            }
+            _ => {
+                resolved.push(target);
+                continue;
+            }
+        };
+        match named.get(&name) {
+            Some(referents) => resolved.extend(
+                referents
+                    .iter()
+                    .map(|referent| object_value(name.clone(), Some(referent.clone()))),
+            ),
+            None => {
+                missing.insert(name);
+            }
+        }
+    }
+    folder.children = resolved;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(
+        index: usize,
+        input: &str,
+        target: EventTarget,
+        output: &str,
+        params: &[&str],
+    ) -> EventExtra {
+        EventExtra {
+            index,
+            enabled: true,
+            input: input.to_string(),
+            delay: 0,
+            target,
+            output: output.to_string(),
+            params: params.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    fn colors() -> [(f32, f32, f32, f32); 64] {
+        let mut colors = [(0., 0., 0., 1.); 64];
+        colors[3] = (1., 0., 0., 1.);
+        colors
+    }
+
+    fn source(script: &Item) -> &str {
+        match script.properties.get("Source") {
+            Some(Property::ProtectedString(ProtectedString(source))) => source,
+            _ => panic!("script has no source"),
+        }
+    }
+
+    fn helpers() -> RbxUuid {
+        helpers_module().referent
+    }
+
+    fn targets(script: &Item) -> Vec<(String, Option<uuid::Uuid>)> {
+        script.children[0]
+            .children
+            .iter()
+            .map(|target| {
+                match (
+                    target.properties.get("Name"),
+                    target.properties.get("Value"),
+                ) {
+                    (Some(Property::String(name)), Some(Property::Ref(Ref(value)))) => {
+                        (name.clone(), value.as_ref().map(|referent| referent.0))
+                    }
+                    _ => panic!("target is not a named ObjectValue"),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn supported_outputs() {
+        for (output, params, call) in [
+            (
+                "setColliding",
+                &["0"][..],
+                "Events.setColliding(target, false)",
+            ),
+            (
+                "setRendering",
+                &["1"][..],
+                "Events.setRendering(target, true)",
+            ),
+            (
+                "setColor",
+                &["3"][..],
+                "Events.setColor(target, Color3.fromRGB(255, 0, 0), 0)",
+            ),
+            (
+                "setEventEnabled",
+                &["0 2", "1"][..],
+                "Events.setEventEnabled(target, {0, 2}, true)",
+            ),
+            (
+                "toggleEventEnabled",
+                &["1"][..],
+                "Events.toggleEventEnabled(target, {1})",
+            ),
+        ] {
+            let mut unsupported = HashSet::new();
+            let script = events_script(
+                &[event(
+                    0,
+                    "onActivate",
+                    EventTarget::SelfBrick,
+                    output,
+                    params,
+                )],
+                &colors(),
+                &helpers(),
+                &mut unsupported,
+            )
+            .unwrap();
+            let source = source(&script);
+            assert!(source.contains(call), "{} is missing {}", output, call);
+            assert!(source.contains("for _, target in ipairs({ brick }) do"));
+            assert!(source.contains("clickDetector.MouseClick"));
+            assert!(unsupported.is_empty(), "{:?}", unsupported);
+        }
+    }
+
+    #[test]
+    fn touch_events_have_a_cooldown() {
+        let script = events_script(
+            &[event(
+                1,
+                "onPlayerTouch",
+                EventTarget::SelfBrick,
+                "setColliding",
+                &["1"],
+            )],
+            &colors(),
+            &helpers(),
+            &mut HashSet::new(),
+        )
+        .unwrap();
+        let source = source(&script);
+        assert!(source.contains("part.Touched:Connect"));
+        assert!(source.contains("now - lastTouched[player] < 0.5"));
+        assert!(source.contains("event1(player)"));
+    }
+
+    #[test]
+    fn unsupported_events_are_reported() {
+        let mut unsupported = HashSet::new();
+        let script = events_script(
+            &[
+                event(0, "onRelay", EventTarget::SelfBrick, "setColliding", &["1"]),
+                event(1, "onActivate", EventTarget::SelfBrick, "fireRelay", &[]),
+                event(2, "onActivate", EventTarget::SelfBrick, "setColor", &["99"]),
+                event(
+                    3,
+                    "onActivate",
+                    EventTarget::Other("Player".to_string()),
+                    "setColliding",
+                    &["1"],
+                ),
+            ],
+            &colors(),
+            &helpers(),
+            &mut unsupported,
+        );
+        assert!(script.is_none());
+        let mut unsupported = unsupported.into_iter().collect::<Vec<_>>();
+        unsupported.sort();
+        assert_eq!(
+            unsupported,
+            ["Player target", "fireRelay", "onRelay", "setColor 99"]
+        );
+    }
+
+    #[test]
+    fn named_targets_are_expanded() {
+        let script = events_script(
+            &[
+                event(
+                    0,
+                    "onActivate",
+                    EventTarget::Named("door".to_string()),
+                    "setColliding",
+                    &["0"],
+                ),
+                event(
+                    1,
+                    "onActivate",
+                    EventTarget::Named("gone".to_string()),
+                    "setRendering",
+                    &["0"],
+                ),
+            ],
+            &colors(),
+            &helpers(),
+            &mut HashSet::new(),
+        )
+        .unwrap();
+        assert!(source(&script).contains("ipairs(Events.getNamed(targets, \"door\"))"));
+        assert_eq!(
+            targets(&script),
+            [("door".to_string(), None), ("gone".to_string(), None)]
+        );
+
+        let doors = vec![Item::new("Part").referent, Item::new("Part").referent];
+        let named = vec![("door".to_string(), doors.clone())]
+            .into_iter()
+            .collect();
+        let mut missing = HashSet::new();
+        let mut model = Item::new("Model");
+        model.children.push(script);
+        let mut items = vec![model];
+        resolve_targets(&mut items, &named, &mut missing);
+
+        assert_eq!(
+            targets(&items[0].children[0]),
+            [
+                ("door".to_string(), Some(doors[0].0)),
+                ("door".to_string(), Some(doors[1].0)),
+            ]
+        );
+        assert_eq!(missing, vec!["gone".to_string()].into_iter().collect());
+    }
+
+    #[test]
+    fn scripts_require_the_shared_helpers() {
+        let module = helpers_module();
+        let script = events_script(
+            &[event(
+                0,
+                "onActivate",
+                EventTarget::SelfBrick,
+                "setColliding",
+                &["0"],
+            )],
+            &colors(),
+            &module.referent,
+            &mut HashSet::new(),
+        )
+        .unwrap();
+        let source = source(&script);
+        assert!(source.contains("require(script:WaitForChild(\"Helpers\").Value)"));
+        assert!(!source.contains("function Events.getParts"));
+        assert!(self::source(&module).contains("function Events.getParts"));
+        match script.children[1].properties.get("Value") {
+            Some(Property::Ref(Ref(Some(referent)))) => assert_eq!(referent.0, module.referent.0),
+            _ => panic!("script doesn't point at the helpers"),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! The extra `+-` lines that follow a brick in a save file, such as lights and
+//! events. `bl_save` keeps these as plain strings.
+
+/// A `+-LIGHT` line
+pub struct LightExtra {
+    /// UI name of the `fxLightData` datablock
+    pub datablock: String,
+    pub enabled: bool,
+}
+
+/// A `+-VEHICLE` line, on bricks that spawn vehicles
+pub struct VehicleExtra {
+    /// UI name of the vehicle datablock
+    pub datablock: String,
+}
+
+/// A `+-ITEM` line, on bricks that spawn items for players to pick up
+pub struct ItemExtra {
+    /// UI name of the item datablock
+    pub datablock: String,
+    /// Milliseconds before the item comes back after it's picked up
+    pub respawn_time: u32,
+}
+
+/// A `+-EVENT` line, which connects an input like clicking the brick to an
+/// output like making a brick solid
+pub struct EventExtra {
+    /// Number of the event on its brick
+    pub index: usize,
+    pub enabled: bool,
+    pub input: String,
+    /// Milliseconds between the input and the output
+    pub delay: u32,
+    pub target: EventTarget,
+    pub output: String,
+    pub params: Vec<String>,
+}
+
+/// What the output of an event acts on
+#[derive(Clone, PartialEq)]
+pub enum EventTarget {
+    /// The brick the event is on
+    SelfBrick,
+    /// Every brick with this name
+    Named(String),
+    /// Targets like `Player` or `Minigame`
+    Other(String),
+}
+
+/// The extra lines of a brick that the converter understands
+#[derive(Default)]
+pub struct Extras {
+    pub light: Option<LightExtra>,
+    pub vehicle: Option<VehicleExtra>,
+    pub item: Option<ItemExtra>,
+    /// BL_ID of the player who owns the brick
+    pub owner: Option<u32>,
+    /// The name given to the brick with the wrench, without the leading `_`
+    pub name: Option<String>,
+    pub events: Vec<EventExtra>,
+}
+
+impl Extras {
+    /// Parse the extra lines of a brick. Lines that aren't understood are
+    /// ignored.
+    pub fn parse(lines: &[String]) -> Extras {
+        let mut extras = Extras::default();
+        for line in lines {
+            let line = match line.strip_prefix("+-") {
+                Some(line) => line,
+                None => continue,
+            };
+            let (kind, rest) = match line.find([' ', '\t']) {
+                Some(i) => (&line[..i], &line[i + 1..]),
+                None => (line, ""),
+            };
+            match kind {
+                "LIGHT" => {
+                    let (name, rest) = split_name(rest);
+                    extras.light = Some(LightExtra {
+                        datablock: name.to_string(),
+                        enabled: rest.trim() != "0",
+                    });
+                }
+                "VEHICLE" => {
+                    let (name, _) = split_name(rest);
+                    extras.vehicle = Some(VehicleExtra {
+                        datablock: name.to_string(),
+                    });
+                }
+                "ITEM" => {
+                    // The item's name is followed by the face it floats over,
+                    // the way it faces and its respawn time
+                    let (name, rest) = split_name(rest);
+                    extras.item = Some(ItemExtra {
+                        datablock: name.to_string(),
+                        respawn_time: rest
+                            .split_whitespace()
+                            .nth(2)
+                            .and_then(|time| time.parse().ok())
+                            .unwrap_or(0),
+                    });
+                }
+                "OWNER" => extras.owner = rest.trim().parse().ok(),
+                "NTOBJECTNAME" => {
+                    let name = object_name(rest);
+                    if !name.is_empty() {
+                        extras.name = Some(name.to_string());
+                    }
+                }
+                "EVENT" => extras.events.extend(parse_event(rest)),
+                _ => {}
+            }
+        }
+        extras
+    }
+}
+
+/// Split a line at the quote that ends the UI name at its start
+fn split_name(s: &str) -> (&str, &str) {
+    match s.find('"') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, ""),
+    }
+}
+
+/// Names are saved with a `_` in front of them
+fn object_name(s: &str) -> &str {
+    let s = s.trim();
+    s.strip_prefix('_').unwrap_or(s)
+}
+
+/// Parse the tab separated fields of an event: index, enabled, input, delay,
+/// target, target name, output and up to four parameters
+fn parse_event(s: &str) -> Option<EventExtra> {
+    let mut fields = s.split('\t');
+    let index = fields.next()?.trim().parse().ok()?;
+    let enabled = fields.next()?.trim() != "0";
+    let input = fields.next()?.trim().to_string();
+    let delay = fields.next()?.trim().parse().unwrap_or(0);
+    let target = fields.next()?.trim();
+    let target_name = object_name(fields.next()?);
+    let output = fields.next()?.trim().to_string();
+    let mut params = fields.map(|p| p.trim().to_string()).collect::<Vec<_>>();
+    while params.last().is_some_and(String::is_empty) {
+        params.pop();
+    }
+    let target = if !target_name.is_empty() {
+        EventTarget::Named(target_name.to_string())
+    } else if target == "Self" {
+        EventTarget::SelfBrick
+    } else {
+        EventTarget::Other(target.to_string())
+    };
+    Some(EventExtra {
+        index,
+        enabled,
+        input,
+        delay,
+        target,
+        output,
+        params,
+    })
+}
@@ -0,0 +1,373 @@
+//! Roblox looks for Blockland's color and shape effects.
+//!
+//! The defaults can be changed with a TOML file that has a table for any of
+//! the effects `pearl`, `chrome`, `glow`, `blink`, `swirl` and `rainbow`:
+//!
+//! ```toml
+//! [chrome]
+//! material = "Foil"        # a Material name or token
+//! reflectance = 0.3
+//!
+//! [blink]
+//! cycle_colors = true      # cycle through colors like rainbow
+//! ```
+
+use crate::error::{Error, Result};
+use crate::types::{Item, Property, ProtectedString};
+
+use serde::Deserialize;
+
+use std::{collections::HashMap, fs, path::Path, str::FromStr};
+
+/// Token of the Plastic material that parts start with
+pub const PLASTIC: u32 = 256;
+
+/// Roblox materials by name
+const MATERIALS: &[(&str, u32)] = &[
+    ("Plastic", 256),
+    ("SmoothPlastic", 272),
+    ("Neon", 288),
+    ("Wood", 512),
+    ("WoodPlanks", 528),
+    ("Marble", 784),
+    ("Slate", 800),
+    ("Concrete", 816),
+    ("Granite", 832),
+    ("Brick", 848),
+    ("Pebble", 864),
+    ("Cobblestone", 880),
+    ("CorrodedMetal", 1040),
+    ("DiamondPlate", 1056),
+    ("Foil", 1072),
+    ("Metal", 1088),
+    ("Grass", 1280),
+    ("Sand", 1296),
+    ("Fabric", 1312),
+    ("Ice", 1536),
+    ("Glass", 1568),
+    ("ForceField", 1584),
+];
+
+/// The token of a material from its name
+pub fn material_token(name: &str) -> Option<u32> {
+    MATERIALS
+        .iter()
+        .find(|(material, _)| material.eq_ignore_ascii_case(name))
+        .map(|(_, token)| *token)
+}
+
+/// Names of the color effects, by their number in save files
+const COLOR_FX_NAMES: &[&str] = &[
+    "none", "pearl", "chrome", "glow", "blink", "swirl", "rainbow",
+];
+
+/// How a color effect looks in Roblox
+#[derive(Clone, Copy, Default)]
+pub struct ColorFxLook {
+    /// Material for parts that would otherwise be Plastic
+    pub material: Option<u32>,
+    /// Reflectance for parts that aren't already reflective
+    pub reflectance: Option<f32>,
+    /// Whether to tag the parts of the brick to have their color cycled by the
+    /// script from `color_cycle_script`
+    pub cycle_colors: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMaterial {
+    Token(u32),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+struct RawLook {
+    material: Option<RawMaterial>,
+    reflectance: Option<f32>,
+    cycle_colors: Option<bool>,
+}
+
+/// Looks for every color effect
+pub struct ColorFxTable {
+    looks: [ColorFxLook; 7],
+}
+
+impl Default for ColorFxTable {
+    fn default() -> Self {
+        let mut looks = [ColorFxLook::default(); 7];
+        looks[1] = ColorFxLook {
+            material: material_token("SmoothPlastic"),
+            reflectance: Some(0.25),
+            cycle_colors: false,
+        };
+        looks[2] = ColorFxLook {
+            material: material_token("Metal"),
+            reflectance: Some(0.6),
+            cycle_colors: false,
+        };
+        looks[3] = ColorFxLook {
+            material: material_token("Neon"),
+            reflectance: None,
+            cycle_colors: false,
+        };
+        looks[6] = ColorFxLook {
+            material: None,
+            reflectance: None,
+            cycle_colors: true,
+        };
+        ColorFxTable { looks }
+    }
+}
+
+impl ColorFxTable {
+    pub fn new() -> Self {
+        ColorFxTable::default()
+    }
+
+    /// Load a TOML file, replacing the settings it mentions
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let source = fs::read_to_string(path)?;
+        self.add_toml(&source, &path.display().to_string())
+    }
+
+    /// Replace the settings in a TOML document. `origin` is used in error
+    /// messages.
+    pub fn add_toml(&mut self, source: &str, origin: &str) -> Result<()> {
+        let error = |reason: String| Error::Definition {
+            origin: origin.to_string(),
+            reason,
+        };
+        let raw: HashMap<String, RawLook> =
+            toml::from_str(source).map_err(|e| error(e.to_string()))?;
+        for (name, raw) in raw {
+            let index = COLOR_FX_NAMES
+                .iter()
+                .position(|fx| *fx == name)
+                .ok_or_else(|| error(format!("unknown color effect {}", name)))?;
+            let look = &mut self.looks[index];
+            match raw.material {
+                Some(RawMaterial::Token(token)) => look.material = Some(token),
+                Some(RawMaterial::Name(name)) => {
+                    look.material = Some(
+                        material_token(&name)
+                            .ok_or_else(|| error(format!("unknown material {}", name)))?,
+                    )
+                }
+                None => {}
+            }
+            if let Some(reflectance) = raw.reflectance {
+                look.reflectance = Some(reflectance);
+            }
+            if let Some(cycle_colors) = raw.cycle_colors {
+                look.cycle_colors = cycle_colors;
+            }
+        }
+        Ok(())
+    }
+
+    /// The look of a color effect. Unknown effects look like no effect.
+    pub fn get(&self, color_fx: u8) -> ColorFxLook {
+        self.looks
+            .get(color_fx as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// CollectionService tag of parts that cycle through colors
+pub const CYCLE_COLORS_TAG: &str = "BlocklandRainbow";
+
+// One script for every tagged part, and a color change every frame, would be
+// too much to send to players in saves with lots of rainbow bricks
+const CYCLE_COLORS_SOURCE: &str = r#"-- Cycles the color of tagged parts like Blockland's rainbow effect
+local CollectionService = game:GetService("CollectionService")
+
+local TAG = "BlocklandRainbow"
+local PERIOD = 4
+local INTERVAL = 0.1
+
+while true do
+	local color = Color3.fromHSV((os.clock() / PERIOD) % 1, 1, 1)
+	for _, part in ipairs(CollectionService:GetTagged(TAG)) do
+		if part:IsA("BasePart") then
+			part.Color = color
+		end
+	end
+	task.wait(INTERVAL)
+end
+"#;
+
+/// The Script that cycles the color of every part tagged with
+/// `CYCLE_COLORS_TAG`. A place only needs one of these.
+pub fn color_cycle_script() -> Item {
+    let mut script = Item::new("Script");
+    script
+        .properties
+        .insert("Name", Property::String("ColorCycle".to_string()));
+    script.properties.insert(
+        "Source",
+        Property::ProtectedString(ProtectedString(CYCLE_COLORS_SOURCE.to_string())),
+    );
+    script
+}
+
+impl ColorFxLook {
+    /// Change the material and reflectance of an item and its children, and
+    /// tag their parts if they cycle through colors
+    pub fn apply(&self, item: &mut Item) {
+        if let Some(material) = self.material {
+            if let Some(Property::Token(token)) = item.properties.get_mut("Material") {
+                if *token == PLASTIC {
+                    *token = material;
+                }
+            }
+        }
+        if let Some(reflectance) = self.reflectance {
+            if let Some(Property::Float(value)) = item.properties.get_mut("Reflectance") {
+                if *value == 0. {
+                    *value = reflectance;
+                }
+            }
+        }
+        if self.cycle_colors && item.properties.contains_key("Material") {
+            item.add_tag(CYCLE_COLORS_TAG);
+        }
+        for child in item.children.iter_mut() {
+            self.apply(child);
+        }
+    }
+}
+
+/// How bricks with the water shape effect are converted
+#[derive(Clone, Copy, PartialEq)]
+pub enum WaterFx {
+    /// Like any other brick
+    Plain,
+    /// Transparent Glass parts that can be walked through
+    Glass,
+    /// Terrain water filling the brick, made by a Script when the game starts.
+    /// The brick itself is hidden.
+    Terrain,
+}
+
+impl FromStr for WaterFx {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(WaterFx::Plain),
+            "glass" => Ok(WaterFx::Glass),
+            "terrain" => Ok(WaterFx::Terrain),
+            _ => Err(format!("Unknown water effect: {}", s)),
+        }
+    }
+}
+
+/// How bricks with the undulo shape effect are converted
+#[derive(Clone, Copy, PartialEq)]
+pub enum UnduloFx {
+    /// Like any other brick
+    Plain,
+    /// Plastic parts become Foil, which is wrinkled like undulo
+    Foil,
+}
+
+impl FromStr for UnduloFx {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(UnduloFx::Plain),
+            "foil" => Ok(UnduloFx::Foil),
+            _ => Err(format!("Unknown undulo effect: {}", s)),
+        }
+    }
+}
+
+/// How shape effects are converted
+#[derive(Clone, Copy)]
+pub struct ShapeFxOptions {
+    pub undulo: UnduloFx,
+    pub water: WaterFx,
+}
+
+impl Default for ShapeFxOptions {
+    fn default() -> Self {
+        ShapeFxOptions {
+            undulo: UnduloFx::Foil,
+            water: WaterFx::Glass,
+        }
+    }
+}
+
+/// Transparency of water made from Glass parts
+pub const WATER_TRANSPARENCY: f32 = 0.5;
+
+// The brick is left in place, invisible and without collision, because
+// other scripts on it and events that target it by name still use it
+const FILL_WATER_SOURCE: &str = r#"-- Fills the brick with terrain water
+local function fill(instance)
+	if instance:IsA("BasePart") then
+		workspace.Terrain:FillBlock(instance.CFrame, instance.Size, Enum.Material.Water)
+	end
+end
+fill(script.Parent)
+for _, descendant in ipairs(script.Parent:GetDescendants()) do
+	fill(descendant)
+end
+"#;
+
+impl ShapeFxOptions {
+    /// Change an item and its children for a shape effect. Returns a Script
+    /// that has to be added to the brick, if the effect needs one.
+    pub fn apply(&self, shape_fx: u8, item: &mut Item) -> Option<Item> {
+        match (shape_fx, self.undulo, self.water) {
+            (1, UnduloFx::Foil, _) => {
+                ColorFxLook {
+                    material: material_token("Foil"),
+                    ..ColorFxLook::default()
+                }
+                .apply(item);
+                None
+            }
+            (2, _, WaterFx::Glass) => {
+                make_water(item, WATER_TRANSPARENCY);
+                None
+            }
+            (2, _, WaterFx::Terrain) => {
+                make_water(item, 1.);
+                let mut script = Item::new("Script");
+                script
+                    .properties
+                    .insert("Name", Property::String("FillWater".to_string()));
+                script.properties.insert(
+                    "Source",
+                    Property::ProtectedString(ProtectedString(FILL_WATER_SOURCE.to_string())),
+                );
+                Some(script)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Make parts see-through Glass that can be walked through
+fn make_water(item: &mut Item, transparency: f32) {
+    if item.properties.contains_key("Material") {
+        item.properties.insert(
+            "Material",
+            Property::Token(material_token("Glass").unwrap()),
+        );
+        item.properties.insert("CanCollide", Property::Bool(false));
+        let item_transparency = item
+            .properties
+            .entry("Transparency")
+            .or_insert(Property::Float(0.));
+        if let Property::Float(t) = item_transparency {
+            *t = t.max(transparency);
+        }
+    }
+    for child in item.children.iter_mut() {
+        make_water(child, transparency);
+    }
+}
@@ -0,0 +1,193 @@
+//! Ways of organizing converted bricks, instead of putting every brick
+//! directly into Workspace.
+//!
+//! Bricks can be grouped by the BL_ID of the player who owns them. Groups are
+//! named after the BL_ID, or after the player if a name file gives one:
+//!
+//! ```toml
+//! 999 = "Badspot"
+//! 12345 = "Builder"
+//! ```
+//!
+//! Bricks can be split into chunks, square cells of a grid on the ground with
+//! a Model for each cell. This keeps the explorer usable for big saves and
+//! lets StreamingEnabled stream bricks in by chunk.
+
+use crate::error::{Error, Result};
+use crate::types::{AttributeValue, CFrame, Item, OptionalCFrame, Property, Vector3};
+
+use nalgebra::Rotation3;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+    str::FromStr,
+};
+
+/// The class of the instances bricks are grouped into
+#[derive(Clone, Copy, PartialEq)]
+pub enum GroupClass {
+    Model,
+    Folder,
+}
+
+impl GroupClass {
+    /// An empty group called `name`
+    pub fn item(self, name: String) -> Item {
+        let mut item = Item::new(match self {
+            GroupClass::Model => "Model",
+            GroupClass::Folder => "Folder",
+        });
+        item.properties.insert("Name", Property::String(name));
+        item
+    }
+}
+
+impl FromStr for GroupClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "model" => Ok(GroupClass::Model),
+            "folder" => Ok(GroupClass::Folder),
+            _ => Err(format!("Unknown group class: {}", s)),
+        }
+    }
+}
+
+/// Names of players, by BL_ID
+#[derive(Default)]
+pub struct OwnerNames {
+    names: HashMap<u32, String>,
+}
+
+impl OwnerNames {
+    pub fn new() -> Self {
+        OwnerNames::default()
+    }
+
+    /// Load a name file, adding to or replacing the names already loaded
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let source = fs::read_to_string(path)?;
+        self.add_toml(&source, &path.display().to_string())
+    }
+
+    /// Add the names in a TOML document. `origin` is used in error messages.
+    pub fn add_toml(&mut self, source: &str, origin: &str) -> Result<()> {
+        let error = |reason: String| Error::Definition {
+            origin: origin.to_string(),
+            reason,
+        };
+        let raw: HashMap<String, String> =
+            toml::from_str(source).map_err(|e| error(e.to_string()))?;
+        for (id, name) in raw {
+            let id = id
+                .trim()
+                .parse()
+                .map_err(|_| error(format!("{:?} is not a BL_ID", id)))?;
+            self.names.insert(id, name);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, id: u32) -> Option<&str> {
+        self.names.get(&id).map(String::as_str)
+    }
+}
+
+/// How to group bricks by owner
+pub struct OwnerGrouping {
+    pub class: GroupClass,
+    pub names: OwnerNames,
+}
+
+impl OwnerGrouping {
+    /// Put the items of every brick into a group for its owner, in order of
+    /// BL_ID. Bricks without an owner are left out of the groups and come
+    /// after them. With a `chunk_size`, the bricks of each owner are split
+    /// into chunks inside of their group.
+    pub fn group(
+        &self,
+        bricks: Vec<(Option<u32>, Vec<Item>)>,
+        chunk_size: Option<f32>,
+    ) -> Vec<Item> {
+        let mut owned: BTreeMap<u32, Vec<Item>> = BTreeMap::new();
+        let mut unowned = vec![];
+        for (owner, items) in bricks {
+            match owner {
+                Some(owner) => owned.entry(owner).or_default().extend(items),
+                None => unowned.extend(items),
+            }
+        }
+
+        let mut groups = Vec::with_capacity(owned.len() + unowned.len());
+        for (owner, items) in owned {
+            let name = match self.names.get(owner) {
+                Some(name) => name.to_string(),
+                None => format!("BL_ID {}", owner),
+            };
+            let mut group = self.class.item(name);
+            group.set_attribute("BlocklandOwner", AttributeValue::Number(owner as f64));
+            group.children = chunk_if_sized(items, chunk_size);
+            groups.push(group);
+        }
+        groups.extend(chunk_if_sized(unowned, chunk_size));
+        groups
+    }
+}
+
+fn chunk_if_sized(items: Vec<Item>, chunk_size: Option<f32>) -> Vec<Item> {
+    match chunk_size {
+        Some(size) => chunk(items, size),
+        None => items,
+    }
+}
+
+/// The position of an item, or of the first part in it
+fn position(item: &Item) -> Option<Vector3> {
+    match item.properties.get("CFrame") {
+        Some(Property::CFrame(cframe)) => Some(cframe.vector),
+        _ => item.children.iter().find_map(position),
+    }
+}
+
+/// Put items into a Model for every `size` by `size` stud cell of a grid on
+/// the X and Z axes, by their position. Models are named after the cell, like
+/// `Chunk 2, -1`, and pivot around the middle of the cell at the height of
+/// their lowest item. Items without a position are kept as they are, after the
+/// chunks.
+pub fn chunk(items: Vec<Item>, size: f32) -> Vec<Item> {
+    let mut cells: BTreeMap<(i32, i32), (f32, Vec<Item>)> = BTreeMap::new();
+    let mut unplaced = vec![];
+    for item in items {
+        match position(&item) {
+            Some(p) => {
+                let cell = ((p.x() / size).floor() as i32, (p.z() / size).floor() as i32);
+                let (bottom, items) = cells.entry(cell).or_insert((f32::INFINITY, vec![]));
+                *bottom = bottom.min(p.y());
+                items.push(item);
+            }
+            None => unplaced.push(item),
+        }
+    }
+
+    let mut chunks = Vec::with_capacity(cells.len() + unplaced.len());
+    for ((x, z), (bottom, items)) in cells {
+        let mut model = Item::new("Model");
+        model
+            .properties
+            .insert("Name", Property::String(format!("Chunk {}, {}", x, z)));
+        model.properties.insert(
+            "WorldPivot",
+            Property::OptionalCFrame(OptionalCFrame(Some(CFrame {
+                vector: Vector3::new((x as f32 + 0.5) * size, bottom, (z as f32 + 0.5) * size),
+                rotation: Rotation3::identity(),
+            }))),
+        );
+        model.children = items;
+        chunks.push(model);
+    }
+    chunks.extend(unplaced);
+    chunks
+}
@@ -0,0 +1,51 @@
+//! Converts Blockland saves (.bls) to Roblox places and models.
+//!
+//! Use a [`Converter`](struct.Converter.html) to turn the bricks of a save into
+//! [`Item`](types/struct.Item.html)s, then write them out with one of the
+//! writers in [`xml`](xml/index.html), [`binary`](binary/index.html) or
+//! [`base`](base/index.html).
+
+#[macro_use]
+extern crate lazy_static;
+
+pub mod base;
+pub mod binary;
+pub mod blb;
+mod convert;
+pub mod definitions;
+mod error;
+pub mod events;
+pub mod extras;
+pub mod fx;
+pub mod grouping;
+pub mod lights;
+mod optimize;
+pub mod prints;
+mod save;
+pub mod specialbricks;
+pub mod types;
+pub mod xml;
+
+pub use convert::*;
+pub use error::{Error, Result};
+pub use save::SaveReader;
+
+use types::{Item, Property};
+
+pub const BRICK_HEIGHT: f32 = 1.2;
+
+pub const CONE_RESOLUTION: u8 = 32;
+
+pub const CONE_WALL_WIDTH: f32 = 0.01;
+
+pub const WEDGE_LIP_SIZE: f32 = 0.125;
+
+/// Wrap `items` in a single Model called `name`
+pub fn model_from_items(name: &str, items: Vec<Item>) -> Item {
+    let mut model = Item::new("Model");
+    model
+        .properties
+        .insert("Name", Property::String(name.to_string()));
+    model.children = items;
+    model
+}
@@ -0,0 +1,257 @@
+//! Lights for bricks with a `+-LIGHT` line, looked up by the UI name of the
+//! light datablock.
+//!
+//! The stock datablocks are built in. Others, or different settings for the
+//! stock ones, can be loaded from a TOML file with a table for every light:
+//!
+//! ```toml
+//! ["Disco Light"]
+//! kind = "spot"            # point (the default) or spot
+//! color = [255, 0, 255]
+//! range = 20               # studs
+//! brightness = 2
+//! angle = 60               # spot lights only, degrees
+//! ```
+
+use crate::error::{Error, Result};
+use crate::types::{Color3, Color3Float, Item, NormalId, Property};
+
+use serde::Deserialize;
+
+use std::{collections::HashMap, fs, path::Path};
+
+/// The longest range Roblox allows for lights
+pub const MAX_LIGHT_RANGE: f32 = 60.;
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LightKind {
+    Point,
+    Spot,
+}
+
+/// How a light datablock looks in Roblox
+#[derive(Clone, Copy)]
+pub struct LightDefinition {
+    pub kind: LightKind,
+    pub color: Color3Float,
+    /// Range in studs, before scaling
+    pub range: f32,
+    pub brightness: f32,
+    /// Angle of spot lights in degrees
+    pub angle: f32,
+}
+
+#[derive(Deserialize)]
+struct RawLight {
+    kind: Option<LightKind>,
+    color: Option<[u8; 3]>,
+    range: Option<f32>,
+    brightness: Option<f32>,
+    angle: Option<f32>,
+}
+
+/// Stock light datablocks: their kind, color, range in studs (two for every
+/// unit of the datablock's radius) and brightness
+const STOCK_LIGHTS: &[(&str, LightKind, [u8; 3], f32, f32)] = &[
+    ("Player's Light", LightKind::Point, [255, 255, 255], 20., 1.),
+    ("Red", LightKind::Point, [255, 0, 0], 30., 2.),
+    ("Orange", LightKind::Point, [255, 128, 0], 30., 2.),
+    ("Yellow", LightKind::Point, [255, 255, 0], 30., 2.),
+    ("Green", LightKind::Point, [0, 255, 0], 30., 2.),
+    ("Cyan", LightKind::Point, [0, 255, 255], 30., 2.),
+    ("Blue", LightKind::Point, [0, 0, 255], 30., 2.),
+    ("Purple", LightKind::Point, [128, 0, 255], 30., 2.),
+    ("Magenta", LightKind::Point, [255, 0, 255], 30., 2.),
+    ("White", LightKind::Point, [255, 255, 255], 30., 2.),
+    ("Red Spot", LightKind::Spot, [255, 0, 0], 50., 3.),
+    ("Yellow Spot", LightKind::Spot, [255, 255, 0], 50., 3.),
+    ("Green Spot", LightKind::Spot, [0, 255, 0], 50., 3.),
+    ("Blue Spot", LightKind::Spot, [0, 0, 255], 50., 3.),
+    ("White Spot", LightKind::Spot, [255, 255, 255], 50., 3.),
+];
+
+const DEFAULT_LIGHT: LightDefinition = LightDefinition {
+    kind: LightKind::Point,
+    color: Color3Float {
+        r: 1.,
+        g: 1.,
+        b: 1.,
+    },
+    range: 16.,
+    brightness: 1.,
+    angle: 90.,
+};
+
+/// Light datablocks by UI name
+pub struct LightTable {
+    lights: HashMap<String, LightDefinition>,
+}
+
+impl Default for LightTable {
+    fn default() -> Self {
+        let lights = STOCK_LIGHTS
+            .iter()
+            .map(|(name, kind, [r, g, b], range, brightness)| {
+                let color = Color3 {
+                    r: *r,
+                    g: *g,
+                    b: *b,
+                    a: 255,
+                };
+                let light = LightDefinition {
+                    kind: *kind,
+                    color: color.into(),
+                    range: *range,
+                    brightness: *brightness,
+                    ..DEFAULT_LIGHT
+                };
+                (name.to_string(), light)
+            })
+            .collect();
+        LightTable { lights }
+    }
+}
+
+impl LightTable {
+    /// A table with the stock light datablocks
+    pub fn new() -> Self {
+        LightTable::default()
+    }
+
+    /// Load a TOML file, adding to or replacing the lights already in the table
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let source = fs::read_to_string(path)?;
+        self.add_toml(&source, &path.display().to_string())
+    }
+
+    /// Add the lights in a TOML document. `origin` is used in error messages.
+    /// Settings that aren't given keep their value from the light being
+    /// replaced, if there is one.
+    pub fn add_toml(&mut self, source: &str, origin: &str) -> Result<()> {
+        let raw: HashMap<String, RawLight> =
+            toml::from_str(source).map_err(|e| Error::Definition {
+                origin: origin.to_string(),
+                reason: e.to_string(),
+            })?;
+        for (name, raw) in raw {
+            let mut light = self.lights.get(&name).copied().unwrap_or(DEFAULT_LIGHT);
+            if let Some(kind) = raw.kind {
+                light.kind = kind;
+            }
+            if let Some([r, g, b]) = raw.color {
+                light.color = Color3 { r, g, b, a: 255 }.into();
+            }
+            if let Some(range) = raw.range {
+                light.range = range;
+            }
+            if let Some(brightness) = raw.brightness {
+                light.brightness = brightness;
+            }
+            if let Some(angle) = raw.angle {
+                light.angle = angle;
+            }
+            self.lights.insert(name, light);
+        }
+        Ok(())
+    }
+
+    pub fn get(&self, datablock: &str) -> Option<&LightDefinition> {
+        self.lights.get(datablock)
+    }
+}
+
+impl LightDefinition {
+    /// A PointLight or SpotLight to parent to a brick
+    pub fn to_item(&self, enabled: bool, scale: f32) -> Item {
+        let mut item = Item::new(match self.kind {
+            LightKind::Point => "PointLight",
+            LightKind::Spot => "SpotLight",
+        });
+        item.properties.insert("Enabled", Property::Bool(enabled));
+        item.properties
+            .insert("Brightness", Property::Float(self.brightness));
+        item.properties
+            .insert("Color", Property::Color3Float(self.color));
+        item.properties.insert(
+            "Range",
+            Property::Float((self.range * scale).min(MAX_LIGHT_RANGE)),
+        );
+        if self.kind == LightKind::Spot {
+            item.properties.insert("Angle", Property::Float(self.angle));
+            item.properties
+                .insert("Face", Property::Token(NormalId::Front.token()));
+        }
+        item
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float(item: &Item, name: &str) -> f32 {
+        match item.properties.get(name) {
+            Some(Property::Float(value)) => *value,
+            _ => panic!("{} is not a float", name),
+        }
+    }
+
+    #[test]
+    fn stock_lights() {
+        let table = LightTable::new();
+        let red = table.get("Red").unwrap();
+        assert!(red.kind == LightKind::Point);
+        assert_eq!((red.color.r, red.color.g, red.color.b), (1., 0., 0.));
+        assert_eq!((red.range, red.brightness), (30., 2.));
+
+        let spot = table.get("Blue Spot").unwrap().to_item(true, 1.);
+        assert_eq!(spot.class, "SpotLight");
+        assert_eq!(float(&spot, "Range"), 50.);
+        assert_eq!(float(&spot, "Brightness"), 3.);
+        assert!(spot.properties.contains_key("Angle"));
+
+        // Ranges grow with the scale, up to what Roblox allows
+        let player = table.get("Player's Light").unwrap();
+        assert_eq!(float(&player.to_item(true, 2.), "Range"), 40.);
+        assert_eq!(float(&player.to_item(true, 4.), "Range"), MAX_LIGHT_RANGE);
+
+        assert!(table.get("Disco Light").is_none());
+    }
+
+    #[test]
+    fn toml_overrides_are_merged() {
+        let mut table = LightTable::new();
+        table
+            .add_toml(
+                r#"
+                ["Red"]
+                range = 10
+
+                ["Disco Light"]
+                kind = "spot"
+                color = [255, 0, 255]
+                angle = 60
+                "#,
+                "lights.toml",
+            )
+            .unwrap();
+
+        // Only the settings that are given change
+        let red = table.get("Red").unwrap();
+        assert_eq!(red.range, 10.);
+        assert_eq!(red.brightness, 2.);
+        assert_eq!((red.color.r, red.color.g, red.color.b), (1., 0., 0.));
+
+        // New lights start from the defaults
+        let disco = table.get("Disco Light").unwrap();
+        assert!(disco.kind == LightKind::Spot);
+        assert_eq!((disco.color.r, disco.color.g, disco.color.b), (1., 0., 1.));
+        assert_eq!((disco.range, disco.brightness, disco.angle), (16., 1., 60.));
+
+        assert!(matches!(
+            table.add_toml("[\"Red\"]\nkind = \"area\"", "lights.toml"),
+            Err(Error::Definition { .. })
+        ));
+    }
+}
@@ -1,614 +1,259 @@
-#[macro_use]
-extern crate lazy_static;
-
-mod specialbricks;
-mod types;
-mod xml;
-
-use specialbricks::SpecialBricksCache;
-use types::{CFrame, Color3, Item, Property, Vector3};
+use bls2rbxlx::{
+	base, binary,
+	blb::BlbBricks,
+	definitions::BrickDefinitions,
+	fx::{ColorFxTable, ShapeFxOptions, UnduloFx, WaterFx},
+	grouping::{GroupClass, OwnerGrouping, OwnerNames},
+	lights::LightTable,
+	model_from_items,
+	prints::PrintMap,
+	xml, Conversion, ConvertOptions, Converter, CullMode, Error, ErrorPolicy, SaveReader,
+};
 
-use bl_save;
-use regex::Regex;
 use structopt::StructOpt;
-use xml::*;
 
 use std::{
-	collections::HashSet,
-	fs::File,
-	io::Write,
+	fs::{self, File},
 	io::{BufReader, BufWriter},
 	path::PathBuf,
+	str::FromStr,
 	time::Instant,
 };
 
-pub const BRICK_HEIGHT: f32 = 1.2;
-
-pub const CONE_RESOLUTION: u8 = 32;
-
-pub const CONE_WALL_WIDTH: f32 = 0.01;
-
-pub const WEDGE_LIP_SIZE: f32 = 0.125;
-
-lazy_static! {
-	// TODO: Lights, prints
-	static ref TALL_BRICK_RE: Regex = Regex::new(r"^(\d+)x(\d+)x(\d+)( Print)?$").unwrap();
-	static ref REGULAR_BRICK_RE: Regex = Regex::new(r"^(\d+?)x(\d+)(F| Base)?( Round)?( Print)?$").unwrap();
-	static ref RAMP_BRICK_RE: Regex = Regex::new(r"^(-)?(\d+)° Ramp (\d+)x(?: Print)?$").unwrap();
-	static ref CORNER_RAMP_BRICK_RE: Regex = Regex::new(r"^(-)?(\d+)° Ramp Corner$").unwrap();
-	static ref CREST_BRICK_RE: Regex = Regex::new(r"(25|45)° Crest (\d)x").unwrap();
+#[derive(PartialEq)]
+enum OutputFormat {
+	/// XML place
+	Rbxlx,
+	/// Binary place
+	Rbxl,
+	/// XML model
+	Rbxmx,
+	/// Binary model
+	Rbxm,
 }
 
-fn items_from_brick(
-	brick: &bl_save::BrickBase,
-	colors: &[(f32, f32, f32, f32); 64],
-	scale: f32,
-	cache: &mut SpecialBricksCache,
-) -> Result<Vec<Item>, ()> {
-	let wedge_lip_size: f32 = WEDGE_LIP_SIZE * BRICK_HEIGHT * scale;
-
-	fn apply_size_and_cframe(cframe: &CFrame, size: &Vector3, item: &mut Item) {
-		item.properties.entry("size").and_modify(|s| match s {
-			Property::Vector3(v) => *v *= *size,
-			_ => unreachable!(),
-		});
-		item.properties.entry("CFrame").and_modify(|c| match c {
-			Property::CFrame(ci) => {
-				*ci *= *size;
-				*ci += *cframe;
-			}
-			_ => unreachable!(),
-		});
-		for child in item.children.iter_mut() {
-			apply_size_and_cframe(cframe, size, child);
-		}
-	}
-
-	fn insert_basics(
-		brick: &bl_save::BrickBase,
-		colors: &[(f32, f32, f32, f32); 64],
-		item: &mut Item,
-	) {
-		let color: Color3 = colors[brick.color_index as usize].into();
-		item.properties
-			.entry("Color3uint8")
-			.or_insert(Property::Color3(color));
-		if !item.properties.contains_key("Transparency") || !brick.rendering {
-			item.properties.insert(
-				"Transparency",
-				Property::Float(if !brick.rendering {
-					1.
-				} else {
-					1. - (color.a as f32 / 255.)
-				}),
-			);
-		}
-		item.properties
-			.entry("CanCollide")
-			.or_insert(Property::Bool(brick.collision));
-		for child in item.children.iter_mut() {
-			insert_basics(brick, colors, child);
-		}
-	}
-
-	match get_brick_type(&brick, scale) {
-		BrickType::Regular { cframe, size, mesh } => Ok(vec![{
-			let mut item = Item::default("Part");
-			item.properties.insert("size", Property::Vector3(size));
-			item.properties.insert("CFrame", Property::CFrame(cframe));
-			insert_basics(&brick, &colors, &mut item);
-			if let RegularBrickMesh::Round = mesh {
-				item.children.push(Item::default("CylinderMesh"))
-			}
-			item
-		}]),
-		BrickType::Ramp {
-			cframe,
-			size,
-			inverted,
-		} => Ok(vec![
-			{
-				// Wedge part of ramp
-				let mut item = Item::default("WedgePart");
-				item.properties.insert(
-					"size",
-					Property::Vector3(size - Vector3::new(0., wedge_lip_size, 0.)),
-				);
-				item.properties.insert(
-					"CFrame",
-					Property::CFrame(
-						cframe
-							+ Vector3::new(
-								0.,
-								wedge_lip_size / if inverted { -2. } else { 2. },
-								0.,
-							) + forward_from_angle(brick.angle) * scale * 0.5,
-					),
-				);
-				insert_basics(&brick, &colors, &mut item);
-
-				item
-			},
-			{
-				// Ramp lip (bottom of ramp)
-				let mut item = Item::default("Part");
-				item.properties.insert(
-					"size",
-					Property::Vector3(Vector3::new(size.x(), wedge_lip_size, size.z())),
-				);
-				item.properties.insert(
-					"CFrame",
-					Property::CFrame(
-						cframe
-							+ Vector3::new(
-								0.,
-								-size.y() / if inverted { -2. } else { 2. }
-									+ wedge_lip_size / if inverted { -2. } else { 2. },
-								0.,
-							) + forward_from_angle(brick.angle) * scale * 0.5,
-					),
-				);
-				insert_basics(&brick, &colors, &mut item);
-
-				item
-			},
-			{
-				// Back of ramp
-				let mut item = Item::default("Part");
-				item.properties.insert(
-					"size",
-					Property::Vector3(Vector3::new(size.x(), size.y(), scale)),
-				);
-				item.properties.insert(
-					"CFrame",
-					Property::CFrame(cframe - (forward_from_angle(brick.angle) * (size.z() / 2.))),
-				);
-				insert_basics(&brick, &colors, &mut item);
-
-				item
-			},
-		]),
-		BrickType::RampCorner {
-			wedge_cframe_1,
-			wedge_cframe_2,
-			corner_cframe,
-			size,
-			inverted,
-		} => {
-			let wedge_offset =
-				Vector3::new(0., wedge_lip_size / 2., 0.) * if inverted { -1. } else { 1. };
-			Ok(vec![
-				{
-					// Corner wedge of corner ramp
-					let mut item = Item::default("CornerWedgePart");
-					item.properties.insert(
-						"size",
-						Property::Vector3(Vector3::new(
-							size.x() - scale,
-							size.y() - wedge_lip_size,
-							size.z() - scale,
-						)),
-					);
-					item.properties.insert(
-						"CFrame",
-						Property::CFrame(
-							corner_cframe
-								+ forward_from_angle(brick.angle) * scale * 0.5
-								+ right_from_angle(brick.angle) * scale * 0.5
-								+ wedge_offset,
-						),
-					);
-					insert_basics(&brick, &colors, &mut item);
-
-					item
-				},
-				{
-					// Corner of corner ramp
-					let mut item = Item::default("Part");
-					item.properties.insert(
-						"size",
-						Property::Vector3(Vector3::new(scale, size.y() - wedge_lip_size, scale)),
-					);
-					item.properties.insert(
-						"CFrame",
-						Property::CFrame(
-							corner_cframe
-								+ forward_from_angle(brick.angle) * (-size.x() / 2.)
-								+ forward_from_angle(brick.angle) * scale * 0.5
-								+ right_from_angle(brick.angle) * (-size.z() / 2.)
-								+ right_from_angle(brick.angle) * scale * 0.5
-								+ wedge_offset,
-						),
-					);
-					insert_basics(&brick, &colors, &mut item);
-
-					item
-				},
-				{
-					// First side of the corner ramp
-					let mut item = Item::default("WedgePart");
-					item.properties.insert(
-						"size",
-						Property::Vector3(Vector3::new(
-							scale,
-							size.y() - wedge_lip_size,
-							size.z() - scale,
-						)),
-					);
-					item.properties.insert(
-						"CFrame",
-						Property::CFrame(
-							wedge_cframe_1
-								+ forward_from_angle(brick.angle) * (-size.x() / 2.)
-								+ forward_from_angle(brick.angle) * scale * 0.5
-								+ right_from_angle(brick.angle) * scale * 0.5
-								+ wedge_offset,
-						),
-					);
-					insert_basics(&brick, &colors, &mut item);
-					item
-				},
-				{
-					// Second side of the corner ramp
-					let mut item = Item::default("WedgePart");
-					item.properties.insert(
-						"size",
-						Property::Vector3(Vector3::new(
-							scale,
-							size.y() - wedge_lip_size,
-							size.z() - scale,
-						)),
-					);
-					item.properties.insert(
-						"CFrame",
-						Property::CFrame(
-							wedge_cframe_2
-								+ forward_from_angle(brick.angle) * scale * 0.5
-								+ right_from_angle(brick.angle) * (-size.z() / 2.)
-								+ right_from_angle(brick.angle) * scale * 0.5
-								+ wedge_offset,
-						),
-					);
-					insert_basics(&brick, &colors, &mut item);
-					item
-				},
-				{
-					// Lip of corner ramp (bottom of ramp)
-					let mut item = Item::default("Part");
-					item.properties.insert(
-						"size",
-						Property::Vector3(Vector3::new(size.x(), wedge_lip_size, size.z())),
-					);
-					item.properties.insert(
-						"CFrame",
-						Property::CFrame(
-							corner_cframe
-								+ Vector3::new(
-									0.,
-									size.y() / if inverted { 2. } else { -2. }
-										+ wedge_lip_size / if inverted { -2. } else { 2. },
-									0.,
-								),
-						),
-					);
-					insert_basics(&brick, &colors, &mut item);
-					item
-				},
-			])
-		}
-		BrickType::Unknown => {
-			let cframe = cframe_from_pos_and_rot(brick.position, brick.angle, false, scale);
-			let size = Vector3::new(1., 1., 1.) * scale;
-			match brick.ui_name.as_str() {
-				// Special bricks
-				// TODO: Ramp crests, Vehicle spawn, small cone, spawn, roads
-				"2x2x2 Cone" => {
-					let mut cone = cache.cone2x2x2();
-
-					apply_size_and_cframe(&cframe, &size, &mut cone);
-					insert_basics(&brick, &colors, &mut cone);
-					Ok(vec![cone])
-				}
-				"1x1 Cone" => {
-					let mut cone = cache.cone1x1();
-					apply_size_and_cframe(&cframe, &size, &mut cone);
-					insert_basics(&brick, &colors, &mut cone);
-					Ok(vec![cone])
-				}
-				"Castle Wall" => {
-					let mut wall = cache.castle_wall();
-					apply_size_and_cframe(&cframe, &size, &mut wall);
-					insert_basics(&brick, &colors, &mut wall);
-					Ok(vec![wall])
-				}
-				"Spawn Point" => {
-					let mut spawn = cache.spawn_point();
-					apply_size_and_cframe(&cframe, &size, &mut spawn);
-					insert_basics(&brick, &colors, &mut spawn);
-					Ok(vec![spawn])
-				}
-				"1x4x5 Window" => {
-					let mut window = cache.window_1x4x3();
-					apply_size_and_cframe(&cframe, &size, &mut window);
-					insert_basics(&brick, &colors, &mut window);
-					Ok(vec![window])
-				}
-				"25° Crest Corner" => {
-					let mut crest = cache.crest_corner_25();
-					apply_size_and_cframe(&cframe, &size, &mut crest);
-					insert_basics(&brick, &colors, &mut crest);
-					Ok(vec![crest])
-				}
-				"25° Crest End" => {
-					let mut crest = cache.crest_end_25();
-					apply_size_and_cframe(&cframe, &size, &mut crest);
-					insert_basics(&brick, &colors, &mut crest);
-					Ok(vec![crest])
-				}
-				"45° Crest Corner" => {
-					let mut crest = cache.crest_corner_45();
-					apply_size_and_cframe(&cframe, &size, &mut crest);
-					insert_basics(&brick, &colors, &mut crest);
-					Ok(vec![crest])
-				}
-				"45° Crest End" => {
-					let mut crest = cache.crest_end_45();
-					apply_size_and_cframe(&cframe, &size, &mut crest);
-					insert_basics(&brick, &colors, &mut crest);
-					Ok(vec![crest])
-				}
-				_ => {
-					if let Some(caps) = CREST_BRICK_RE.captures(&brick.ui_name) {
-						let height = caps.get(1).unwrap().as_str();
-						let height = if height == "25" { 2. / 3. } else { 1. };
-						let length = caps.get(2).unwrap().as_str().parse::<u8>().unwrap();
-						let mut crest = specialbricks::generate_crest(height, length);
-						apply_size_and_cframe(&cframe, &size, &mut crest);
-						insert_basics(&brick, &colors, &mut crest);
-						Ok(vec![crest])
-					} else {
-						Err(())
-					}
-				}
-			}
+impl OutputFormat {
+	fn extension(&self) -> &'static str {
+		match self {
+			OutputFormat::Rbxlx => "rbxlx",
+			OutputFormat::Rbxl => "rbxl",
+			OutputFormat::Rbxmx => "rbxmx",
+			OutputFormat::Rbxm => "rbxm",
 		}
 	}
 }
 
-fn get_brick_type(brick: &bl_save::BrickBase, scale: f32) -> BrickType {
-	if let Some(caps) = TALL_BRICK_RE.captures(&brick.ui_name) {
-		let x: f32 = caps.get(1).unwrap().as_str().parse().unwrap();
-		let z: f32 = caps.get(2).unwrap().as_str().parse().unwrap();
-		let y = caps.get(3).unwrap().as_str().parse::<f32>().unwrap() * BRICK_HEIGHT;
-		BrickType::Regular {
-			size: Vector3::new(x, y, z) * scale,
-			cframe: cframe_from_pos_and_rot(
-				brick.position,
-				(brick.angle + if caps.get(4).is_some() { 1 } else { 0 }) % 4,
-				false,
-				scale,
-			),
-			mesh: RegularBrickMesh::Block,
-		}
-	} else if let Some(caps) = REGULAR_BRICK_RE.captures(&brick.ui_name) {
-		let x: f32 = caps.get(1).unwrap().as_str().parse().unwrap(); // These will never panic, check the RE
-		let z: f32 = caps.get(2).unwrap().as_str().parse().unwrap();
-		let y = if caps.get(3).is_some() {
-			0.4
-		} else {
-			BRICK_HEIGHT
-		};
-		BrickType::Regular {
-			size: Vector3::new(x, y, z) * scale,
-			cframe: cframe_from_pos_and_rot(
-				brick.position,
-				(brick.angle + if caps.get(5).is_some() { 1 } else { 0 }) % 4,
-				false,
-				scale,
-			),
-			mesh: if caps.get(4).is_some() {
-				RegularBrickMesh::Round
-			} else {
-				RegularBrickMesh::Block
-			},
-		}
-	} else if let Some(caps) = RAMP_BRICK_RE.captures(&brick.ui_name) {
-		let angle = parse_ramp_angle(caps.get(2).unwrap().as_str()).expect("Unknown ramp angle");
-
-		let x = caps.get(3).unwrap().as_str().parse::<u8>().unwrap();
-		let z = match angle {
-			RampAngle::Angle25 => 2,
-			_ => 1,
-		};
-		let y = BRICK_HEIGHT
-			* match angle {
-				RampAngle::Angle25 | RampAngle::Angle45 => 1.,
-				RampAngle::Angle72 => 3.,
-				RampAngle::Angle80 => 5.,
-			};
-		let inverted = caps.get(1).is_some();
-		BrickType::Ramp {
-			size: Vector3::new(x as f32, y, z as f32) * scale,
-			cframe: cframe_from_pos_and_rot(brick.position, brick.angle, inverted, scale),
-			inverted,
-		}
-	} else if let Some(caps) = CORNER_RAMP_BRICK_RE.captures(&brick.ui_name) {
-		let angle =
-			parse_ramp_angle(caps.get(2).unwrap().as_str()).expect("Unknown corner ramp angle");
+impl FromStr for OutputFormat {
+	type Err = String;
 
-		let x = match angle {
-			RampAngle::Angle25 => 3.,
-			_ => 2.,
-		};
-		let z = x;
-		let y = BRICK_HEIGHT
-			* match angle {
-				RampAngle::Angle25 | RampAngle::Angle45 => 1.,
-				RampAngle::Angle72 => 3.,
-				RampAngle::Angle80 => 5.,
-			};
-		let inverted = caps.get(1).is_some();
-		BrickType::RampCorner {
-			size: Vector3::new(x, y, z) * scale,
-			corner_cframe: cframe_from_pos_and_rot(
-				brick.position,
-				(brick.angle + if inverted { 3 } else { 2 }) % 4,
-				inverted,
-				scale,
-			),
-			wedge_cframe_1: cframe_from_pos_and_rot(
-				brick.position,
-				(brick.angle + 1) % 4,
-				inverted,
-				scale,
-			),
-			wedge_cframe_2: cframe_from_pos_and_rot(brick.position, brick.angle, inverted, scale),
-			inverted,
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"rbxlx" => Ok(OutputFormat::Rbxlx),
+			"rbxl" => Ok(OutputFormat::Rbxl),
+			"rbxmx" => Ok(OutputFormat::Rbxmx),
+			"rbxm" => Ok(OutputFormat::Rbxm),
+			_ => Err(format!("Unknown output format: {}", s)),
 		}
-	} else {
-		BrickType::Unknown
-	}
-}
-
-fn forward_from_angle(angle: u8) -> Vector3 {
-	match angle {
-		0 => Vector3::new(0., 0., -1.),
-		1 => Vector3::new(1., 0., 0.),
-		2 => Vector3::new(0., 0., 1.),
-		_ => Vector3::new(-1., 0., 0.),
-	}
-}
-
-fn right_from_angle(angle: u8) -> Vector3 {
-	match angle {
-		0 => Vector3::new(1., 0., 0.),
-		1 => Vector3::new(0., 0., 1.),
-		2 => Vector3::new(-1., 0., 0.),
-		_ => Vector3::new(0., 0., -1.),
-	}
-}
-
-fn cframe_from_pos_and_rot(pos: (f32, f32, f32), angle: u8, inverted: bool, scale: f32) -> CFrame {
-	let x = pos.0 * 2. * scale;
-	let y = pos.2 * 2. * scale;
-	let z = -pos.1 * 2. * scale;
-	let mut rot = nalgebra::Rotation3::new(nalgebra::Vector3::new(
-		0.,
-		-(angle as f32 * <f32 as nalgebra::RealField>::frac_pi_2()),
-		0.,
-	));
-	rot *= nalgebra::Rotation3::new(nalgebra::Vector3::new(
-		0.,
-		0.,
-		if inverted {
-			<f32 as nalgebra::RealField>::pi()
-		} else {
-			0.
-		},
-	));
-	CFrame {
-		vector: Vector3::new(x, y, z),
-		rotation: rot,
 	}
 }
 
-enum RampAngle {
-	Angle25,
-	Angle45,
-	Angle72,
-	Angle80,
-}
-
-fn parse_ramp_angle(s: &str) -> Option<RampAngle> {
-	match s {
-		"25" => Some(RampAngle::Angle25),
-		"45" => Some(RampAngle::Angle45),
-		"72" => Some(RampAngle::Angle72),
-		"80" => Some(RampAngle::Angle80),
-		_ => None,
-	}
-}
-
-enum RegularBrickMesh {
-	Block,
-	Round,
-}
-
-enum BrickType {
-	Regular {
-		cframe: CFrame,
-		size: Vector3,
-		mesh: RegularBrickMesh,
-	},
-	Ramp {
-		cframe: CFrame,
-		size: Vector3,
-		inverted: bool,
-	},
-	RampCorner {
-		corner_cframe: CFrame,
-		wedge_cframe_1: CFrame,
-		wedge_cframe_2: CFrame,
-		size: Vector3,
-		inverted: bool,
-	},
-	Unknown,
-}
-
 #[derive(StructOpt)]
 /// Convert .bls files (Blockland save) to .rbxlx (Roblox save)
 struct Args {
 	#[structopt(parse(from_os_str))]
 	/// File to convert
 	input: PathBuf,
-	#[structopt(parse(from_os_str), default_value = "result.rbxlx")]
-	/// File that will be written to
-	output: PathBuf,
+	#[structopt(parse(from_os_str))]
+	/// File that will be written to [default: result.<format>]
+	output: Option<PathBuf>,
 	#[structopt(short, long, default_value = "1")]
 	/// How many Roblox studs a Blockland unit will correspond to
 	scale: f32,
 	#[structopt(short, long)]
 	/// Show no output on the command line
 	quiet: bool,
+	#[structopt(
+		short,
+		long,
+		default_value = "rbxlx",
+		possible_values = &["rbxlx", "rbxl", "rbxmx", "rbxm"]
+	)]
+	/// Format of the output file. Places contain the whole map, models contain a single Model
+	/// named after the input file that can be inserted into an existing game
+	format: OutputFormat,
+	#[structopt(long, parse(from_os_str))]
+	/// Existing .rbxlx place to insert the converted bricks into. Everything else in the place is
+	/// kept as is. Only works with the rbxlx format
+	base: Option<PathBuf>,
+	#[structopt(long, default_value = "Workspace")]
+	/// Where to insert the converted bricks when using --base, as a list of names separated by
+	/// slashes (such as Workspace/Map). Missing folders are created
+	parent: String,
+	#[structopt(long)]
+	/// Leave out bricks that can't be read or converted instead of stopping, and list them at
+	/// the end
+	skip_errors: bool,
+	#[structopt(short, long = "bricks", parse(from_os_str))]
+	/// Brick definition file (.toml) or directory of definition files to load. Can be given more
+	/// than once, later files take precedence
+	bricks: Vec<PathBuf>,
+	#[structopt(long, parse(from_os_str))]
+	/// Blockland .blb file, add-on .zip or directory of either to use for bricks that aren't
+	/// otherwise known. UI names are read from the add-on's .cs files. Can be given more than once
+	blb: Vec<PathBuf>,
+	#[structopt(long, parse(from_os_str))]
+	/// TOML file mapping print names to Roblox asset ids or image paths, used to put Decals on
+	/// print bricks. Paths are relative to Roblox's content folder
+	prints: Option<PathBuf>,
+	#[structopt(long, parse(from_os_str))]
+	/// TOML file with light datablocks to add to or replace the built in ones
+	lights: Option<PathBuf>,
+	#[structopt(long, parse(from_os_str))]
+	/// TOML file that changes the materials and scripts used for color effects (pearl, chrome,
+	/// glow, blink, swirl and rainbow)
+	color_fx: Option<PathBuf>,
+	#[structopt(long, default_value = "glass", possible_values = &["glass", "terrain", "plain"])]
+	/// How to convert bricks with the water effect: see-through Glass parts, Terrain water that a
+	/// Script fills in when the game starts, or plain parts
+	water: WaterFx,
+	#[structopt(long, default_value = "foil", possible_values = &["foil", "plain"])]
+	/// How to convert bricks with the undulo effect: Foil parts or plain parts
+	undulo: UnduloFx,
+	#[structopt(long)]
+	/// Store where each brick came from (UI name, color, owner, angle, baseplate, print and
+	/// position in the save) in attributes
+	attributes: bool,
+	#[structopt(long, possible_values = &["model", "folder"])]
+	/// Put the bricks of each owner into a Model or Folder named after their BL_ID
+	group_by_owner: Option<GroupClass>,
+	#[structopt(long, parse(from_os_str))]
+	/// TOML file mapping BL_IDs to player names, used to name the groups of --group-by-owner
+	owner_names: Option<PathBuf>,
+	#[structopt(long)]
+	/// Split the bricks into Models for square cells of a grid, this many studs wide. Makes big
+	/// saves easier to work with and to stream
+	chunk_size: Option<f32>,
+	#[structopt(long)]
+	/// Merge regular bricks that are next to or on top of each other and look and act the same
+	/// into bigger parts, to reduce the part count
+	merge: bool,
+	#[structopt(long, possible_values = &["remove", "hide"])]
+	/// Cull bricks that are completely covered by other solid bricks and can't be seen or touched.
+	/// They're either removed, or made invisible and moved into a "Hidden bricks" folder
+	cull: Option<CullMode>,
+	#[structopt(long)]
+	/// Leave out bricks that are exact copies of another brick in the same place, such as the ones
+	/// left behind by duplicators
+	dedup: bool,
+	#[structopt(long)]
+	/// List bricks that partly overlap each other
+	warn_overlaps: bool,
 }
 
 fn main() {
 	let args = Args::from_args();
+	if args.base.is_some() && args.format != OutputFormat::Rbxlx {
+		eprintln!("--base can only be used with the rbxlx format");
+		std::process::exit(1);
+	}
+	if args.chunk_size.is_some_and(|size| size.is_nan() || size <= 0.) {
+		eprintln!("--chunk-size must be more than 0");
+		std::process::exit(1);
+	}
+	if let Err(e) = run(args) {
+		eprintln!("Error: {}", e);
+		std::process::exit(1);
+	}
+}
+
+fn run(args: Args) -> Result<(), Error> {
 	let total_start_time = Instant::now();
-	let file = BufReader::new(File::open(&args.input).unwrap());
+	let file = BufReader::new(File::open(&args.input)?);
 	let parse_start_time = Instant::now();
-	let reader = bl_save::Reader::new(file).unwrap();
+	let reader = SaveReader::new(file)?;
 	let input_parsed_time = Instant::now();
-	let colors = *reader.colors();
-	let num_bricks = reader.brick_count().unwrap();
-	let mut cache = SpecialBricksCache::new();
-
-	let mut items = Vec::<Item>::new();
-	let mut unknown_bricks = HashSet::<String>::new();
-
+	// The claimed count can be missing or wrong, so it's only used for progress
+	let num_bricks = reader.brick_count().filter(|&count| count > 0);
+	let mut definitions = BrickDefinitions::new();
+	for path in args.bricks.iter() {
+		definitions.load(path)?;
+	}
+	let mut blb_bricks = BlbBricks::new();
+	for path in args.blb.iter() {
+		blb_bricks.load(path)?;
+	}
+	if !args.quiet {
+		for ambiguous in blb_bricks.ambiguous() {
+			eprintln!("Ambiguous .blb file for {}", ambiguous);
+		}
+	}
+	if !blb_bricks.is_empty() && !args.quiet {
+		println!("Loaded {} bricks from .blb files", blb_bricks.len());
+	}
+	let mut prints = PrintMap::new();
+	if let Some(path) = &args.prints {
+		prints.load(path)?;
+	}
+	let mut lights = LightTable::new();
+	if let Some(path) = &args.lights {
+		lights.load(path)?;
+	}
+	let mut color_fx = ColorFxTable::new();
+	if let Some(path) = &args.color_fx {
+		color_fx.load(path)?;
+	}
+	let mut owner_names = OwnerNames::new();
+	if let Some(path) = &args.owner_names {
+		owner_names.load(path)?;
+	}
+	let mut converter = Converter::new(ConvertOptions {
+		scale: args.scale,
+		error_policy: if args.skip_errors {
+			ErrorPolicy::SkipAndReport
+		} else {
+			ErrorPolicy::Abort
+		},
+		definitions,
+		blb_bricks,
+		prints,
+		lights,
+		color_fx,
+		shape_fx: ShapeFxOptions {
+			undulo: args.undulo,
+			water: args.water,
+		},
+		brick_attributes: args.attributes,
+		owner_grouping: args.group_by_owner.map(|class| OwnerGrouping {
+			class,
+			names: owner_names,
+		}),
+		chunk_size: args.chunk_size,
+		merge_bricks: args.merge,
+		cull: args.cull,
+		remove_duplicates: args.dedup,
+		find_overlaps: args.warn_overlaps,
+	});
+
+	let quiet = args.quiet;
 	let conversion_start_time = Instant::now();
-	for (i, brick) in reader.enumerate() {
-		let brick = brick.unwrap();
-		match items_from_brick(&brick.base, &colors, args.scale, &mut cache) {
-			Ok(new_items) => {
-				for item in new_items {
-					items.push(item);
-				}
-			}
-			Err(()) => {
-				unknown_bricks.insert(brick.base.ui_name);
+	let colors = *reader.colors();
+	let bricks = reader.enumerate().map(|(i, brick)| {
+		if !quiet {
+			match num_bricks {
+				Some(count) => println!(
+					"{} bricks processed ({}%)",
+					i + 1,
+					(i + 1) as f32 / count as f32 * 100.
+				),
+				None => println!("{} bricks processed", i + 1),
 			}
 		}
-		if !args.quiet {
-			println!(
-				"{} bricks processed ({}%)",
-				i + 1,
-				(i + 1) as f32 / num_bricks as f32 * 100.
-			);
-		}
-	}
+		brick
+	});
+	let Conversion { items, report } = converter.convert_bricks(bricks, &colors)?;
 	let conversion_end_time = Instant::now();
 
+	let unknown_bricks = report.unknown_bricks;
 	if !unknown_bricks.is_empty() && !args.quiet {
 		eprintln!(
 			"!! {} brick types in this file could not be converted !!",
@@ -620,21 +265,95 @@ fn main() {
 			eprintln!("Unknown brick type: {}", unknown_brick);
 		}
 	}
+	if !report.unmapped_prints.is_empty() && !args.quiet {
+		eprintln!(
+			"!! {} prints in this file have no texture !!",
+			report.unmapped_prints.len()
+		);
+		let mut unmapped_prints = report.unmapped_prints.iter().collect::<Vec<_>>();
+		unmapped_prints.sort();
+		for print in unmapped_prints {
+			eprintln!("Unmapped print: {}", print);
+		}
+	}
+	if !report.unknown_lights.is_empty() && !args.quiet {
+		eprintln!(
+			"!! {} light types in this file could not be converted !!",
+			report.unknown_lights.len()
+		);
+		let mut unknown_lights = report.unknown_lights.iter().collect::<Vec<_>>();
+		unknown_lights.sort();
+		for light in unknown_lights {
+			eprintln!("Unknown light: {}", light);
+		}
+	}
+	if !report.unsupported_events.is_empty() && !args.quiet {
+		eprintln!(
+			"!! {} event inputs, outputs or targets in this file could not be converted !!",
+			report.unsupported_events.len()
+		);
+		let mut unsupported_events = report.unsupported_events.iter().collect::<Vec<_>>();
+		unsupported_events.sort();
+		for event in unsupported_events {
+			eprintln!("Unsupported event: {}", event);
+		}
+	}
+	if !report.missing_targets.is_empty() && !args.quiet {
+		let mut missing_targets = report.missing_targets.iter().collect::<Vec<_>>();
+		missing_targets.sort();
+		for name in missing_targets {
+			eprintln!("Events target a brick named {} that doesn't exist", name);
+		}
+	}
+	if !report.overlaps.is_empty() && !args.quiet {
+		eprintln!(
+			"!! {} pairs of bricks in this file overlap !!",
+			report.overlaps.len()
+		);
+		for (a, b) in report.overlaps.iter() {
+			eprintln!("{} overlaps {}", a, b);
+		}
+	}
+	if !report.skipped.is_empty() && !args.quiet {
+		eprintln!(
+			"!! {} bricks in this file were skipped because of errors !!",
+			report.skipped.len()
+		);
+		for error in report.skipped.iter() {
+			eprintln!("{}", error);
+		}
+	}
 
-	let result_file = File::create(&args.output).unwrap();
+	let format = args.format;
+	let output = args
+		.output
+		.unwrap_or_else(|| PathBuf::from(format!("result.{}", format.extension())));
+	let result_file = File::create(&output)?;
 	let mut result_buf = BufWriter::new(result_file);
-	write!(&mut result_buf, "{}", START_XML).unwrap();
-	write!(
-		&mut result_buf,
-		"{}",
-		items
-			.into_iter()
-			.map(|i| i.to_string())
-			.collect::<Vec::<_>>()
-			.join("\n")
-	)
-	.unwrap();
-	write!(&mut result_buf, "{}", END_XML).unwrap();
+	match format {
+		OutputFormat::Rbxlx => match &args.base {
+			Some(base) => {
+				let base = fs::read_to_string(base)?;
+				let path = args.parent.split('/').collect::<Vec<_>>();
+				base::write_into_place(&mut result_buf, &base, &path, items)
+			}
+			None => xml::write_place(&mut result_buf, items),
+		},
+		OutputFormat::Rbxl => binary::write_place(&mut result_buf, items),
+		OutputFormat::Rbxmx | OutputFormat::Rbxm => {
+			let name = args
+				.input
+				.file_stem()
+				.map(|s| s.to_string_lossy().into_owned())
+				.unwrap_or_else(|| "Model".to_string());
+			let model = model_from_items(&name, items);
+			if let OutputFormat::Rbxmx = format {
+				xml::write_model(&mut result_buf, &model)
+			} else {
+				binary::write_roots(&mut result_buf, &[model])
+			}
+		}
+	}?;
 	let total_end_time = Instant::now();
 	if !args.quiet {
 		let total_duration = total_end_time.duration_since(total_start_time);
@@ -643,17 +362,27 @@ fn main() {
 		println!();
 		println!(
 			"Parsed {} bricks in {}ms",
-			num_bricks,
+			report.brick_count,
 			parse_duration.as_millis()
 		);
-		println!(
-			"Converted to rbxlx in {}ms",
-			conversion_duration.as_millis()
-		);
+		if report.duplicates > 0 {
+			println!("Removed {} duplicate bricks", report.duplicates);
+		}
+		if let Some(culled) = report.culled_parts {
+			println!("Culled {} parts covered by other bricks", culled);
+		}
+		if let Some(merged) = report.merged_parts {
+			println!(
+				"Merged bricks from {} parts into {} parts",
+				merged.before, merged.after
+			);
+		}
+		println!("Converted in {}ms", conversion_duration.as_millis());
 		println!(
 			"Total time (including read/write time): {}s, {}ms",
 			total_duration.as_secs(),
 			total_duration.subsec_millis()
 		);
 	}
+	Ok(())
 }
@@ -0,0 +1,550 @@
+//! Passes over converted bricks that reduce the number of parts, or find
+//! bricks that are in each other's way.
+
+use crate::convert::{BrickLocation, ConvertedBrick};
+use crate::types::{CFrame, Item, Property, Vector3};
+
+use nalgebra::Rotation3;
+
+use std::collections::{HashMap, HashSet};
+
+/// Coordinates are compared in hundredths of a stud at scale 1, which is
+/// finer than anything bricks are placed on
+const GRID: f32 = 100.;
+
+/// Count the parts in some items and everything in them
+pub fn count_parts(items: &[Item]) -> usize {
+    items
+        .iter()
+        .map(|item| {
+            let own = if item.properties.contains_key("size") && item.class != "Model" {
+                1
+            } else {
+                0
+            };
+            own + count_parts(&item.children)
+        })
+        .sum()
+}
+
+/// An axis aligned box on the grid, from `min` to `max`
+#[derive(Clone, Copy)]
+struct GridBox {
+    min: [i64; 3],
+    max: [i64; 3],
+}
+
+impl GridBox {
+    fn overlaps(&self, other: &GridBox) -> bool {
+        (0..3).all(|axis| self.min[axis] < other.max[axis] && other.min[axis] < self.max[axis])
+    }
+}
+
+/// The box a brick fills, if the brick is a single block Part with nothing
+/// in it, like the ones regular bricks become, and it isn't rotated by
+/// anything other than quarter turns
+fn grid_box(item: &Item, scale: f32) -> Option<GridBox> {
+    if !item.children.is_empty() {
+        return None;
+    }
+    // Tags and attributes belong to a single brick
+    if item.properties.contains_key("Tags") || item.properties.contains_key("AttributesSerialize") {
+        return None;
+    }
+    block_box(item, scale)
+}
+
+/// The box a block Part fills, if it isn't rotated by anything other than
+/// quarter turns
+fn block_box(item: &Item, scale: f32) -> Option<GridBox> {
+    if item.class != "Part" {
+        return None;
+    }
+    let (cframe, size) = match (
+        item.properties.get("shape"),
+        item.properties.get("CFrame"),
+        item.properties.get("size"),
+    ) {
+        (
+            Some(Property::Token(1)),
+            Some(Property::CFrame(cframe)),
+            Some(Property::Vector3(size)),
+        ) => (cframe, size),
+        _ => return None,
+    };
+
+    let mut half = [0.; 3];
+    for (row, half) in half.iter_mut().enumerate() {
+        for col in 0..3 {
+            let r = cframe.rotation[(row, col)];
+            if r.abs() > 1e-4 && (r.abs() - 1.).abs() > 1e-4 {
+                return None;
+            }
+            *half += r.abs().round() * size.0[col] / 2.;
+        }
+    }
+    let unit = GRID / scale;
+    let center = cframe.vector.0;
+    let mut min = [0; 3];
+    let mut max = [0; 3];
+    for axis in 0..3 {
+        min[axis] = ((center[axis] - half[axis]) * unit).round() as i64;
+        max[axis] = ((center[axis] + half[axis]) * unit).round() as i64;
+    }
+    Some(GridBox { min, max })
+}
+
+/// Everything about a part that has to match for it to be merged with
+/// another one, other than where it is
+fn merge_key(owner: Option<u32>, item: &Item) -> String {
+    let mut properties = item
+        .properties
+        .iter()
+        .filter(|(name, _)| **name != "CFrame" && **name != "size")
+        .map(|(name, property)| property.property_to_string(name))
+        .collect::<Vec<_>>();
+    properties.sort();
+    format!("{:?}{}", owner, properties.concat())
+}
+
+/// Merge boxes that touch along `axis` with a whole face. Returns whether any
+/// boxes were merged.
+fn merge_along(boxes: &mut Vec<GridBox>, axis: usize) -> bool {
+    let others = [(axis + 1) % 3, (axis + 2) % 3];
+    let face = |b: &GridBox| {
+        (
+            b.min[others[0]],
+            b.max[others[0]],
+            b.min[others[1]],
+            b.max[others[1]],
+        )
+    };
+    boxes.sort_by_key(|b| (face(b), b.min[axis]));
+
+    let mut merged: Vec<GridBox> = Vec::with_capacity(boxes.len());
+    let mut changed = false;
+    for b in boxes.drain(..) {
+        match merged.last_mut() {
+            Some(last) if face(last) == face(&b) && last.max[axis] == b.min[axis] => {
+                last.max[axis] = b.max[axis];
+                changed = true;
+            }
+            _ => merged.push(b),
+        }
+    }
+    *boxes = merged;
+    changed
+}
+
+/// Merge bricks that are single block Parts with the same properties and
+/// owner, and are next to or on top of each other, into bigger Parts. Bricks
+/// are merged greedily along X, then Z, then Y, until nothing more can be
+/// merged. Merged parts keep the place of the first brick they're made of.
+pub fn merge_bricks(bricks: Vec<ConvertedBrick>, scale: f32) -> Vec<ConvertedBrick> {
+    let mut sets: HashMap<String, (usize, Vec<GridBox>)> = HashMap::new();
+    let mut result: Vec<Option<ConvertedBrick>> = Vec::with_capacity(bricks.len());
+    for brick in bricks {
+        let mergeable = match &brick.items[..] {
+            [item] => grid_box(item, scale).map(|b| (merge_key(brick.owner, item), b)),
+            _ => None,
+        };
+        match mergeable {
+            Some((key, b)) => {
+                let index = result.len();
+                let (first, boxes) = sets.entry(key).or_insert((index, vec![]));
+                boxes.push(b);
+                if *first == index {
+                    result.push(Some(brick));
+                } else {
+                    result.push(None);
+                }
+            }
+            None => result.push(Some(brick)),
+        }
+    }
+
+    let unit = GRID / scale;
+    for (_, (first, mut boxes)) in sets {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for axis in [0, 2, 1] {
+                changed |= merge_along(&mut boxes, axis);
+            }
+        }
+        let template = result[first].take().unwrap();
+        let mut parts = Vec::with_capacity(boxes.len());
+        for b in boxes {
+            let mut part = template.items[0].clone();
+            part.renew_referents();
+            let coordinate = |axis: usize| (b.min[axis] + b.max[axis]) as f32 / 2. / unit;
+            let length = |axis: usize| (b.max[axis] - b.min[axis]) as f32 / unit;
+            part.properties.insert(
+                "size",
+                Property::Vector3(Vector3::new(length(0), length(1), length(2))),
+            );
+            part.properties.insert(
+                "CFrame",
+                Property::CFrame(CFrame {
+                    vector: Vector3::new(coordinate(0), coordinate(1), coordinate(2)),
+                    rotation: Rotation3::identity(),
+                }),
+            );
+            parts.push(part);
+        }
+        result[first] = Some(ConvertedBrick {
+            items: parts,
+            ..template
+        });
+    }
+    result.into_iter().flatten().collect()
+}
+
+/// Side length of the cells of the spatial index, in grid units
+const CELL: i64 = 4 * GRID as i64;
+
+/// Boxes, found by the cells of a grid they overlap
+#[derive(Default)]
+struct SpatialIndex {
+    boxes: Vec<GridBox>,
+    cells: HashMap<[i64; 3], Vec<usize>>,
+}
+
+impl SpatialIndex {
+    fn cells(b: &GridBox) -> impl Iterator<Item = [i64; 3]> {
+        let (min, max) = (b.min, b.max);
+        let range =
+            move |axis: usize| min[axis].div_euclid(CELL)..=(max[axis] - 1).div_euclid(CELL);
+        range(0).flat_map(move |x| range(1).flat_map(move |y| range(2).map(move |z| [x, y, z])))
+    }
+
+    fn insert(&mut self, b: GridBox) {
+        let index = self.boxes.len();
+        self.boxes.push(b);
+        for cell in SpatialIndex::cells(&b) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+    }
+
+    /// Indices of the boxes that overlap `area`
+    fn query_indices(&self, area: &GridBox) -> Vec<usize> {
+        let mut found = SpatialIndex::cells(area)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        found.dedup();
+        found.retain(|&index| self.boxes[index].overlaps(area));
+        found
+    }
+
+    /// Boxes that overlap `area`
+    fn query(&self, area: &GridBox) -> Vec<GridBox> {
+        self.query_indices(area)
+            .into_iter()
+            .map(|index| self.boxes[index])
+            .collect()
+    }
+}
+
+/// Whether the rectangle from `min` to `max` is covered by `rects`
+fn covered(min: [i64; 2], max: [i64; 2], rects: &[([i64; 2], [i64; 2])]) -> bool {
+    let cuts = |axis: usize| {
+        let mut cuts = vec![min[axis], max[axis]];
+        for (rect_min, rect_max) in rects {
+            cuts.push(rect_min[axis].clamp(min[axis], max[axis]));
+            cuts.push(rect_max[axis].clamp(min[axis], max[axis]));
+        }
+        cuts.sort_unstable();
+        cuts.dedup();
+        cuts
+    };
+    let (xs, ys) = (cuts(0), cuts(1));
+    xs.windows(2).all(|x| {
+        ys.windows(2).all(|y| {
+            rects.iter().any(|(rect_min, rect_max)| {
+                rect_min[0] <= x[0]
+                    && x[1] <= rect_max[0]
+                    && rect_min[1] <= y[0]
+                    && y[1] <= rect_max[1]
+            })
+        })
+    })
+}
+
+/// Whether every face of `b` is covered by the boxes in `index`
+fn enclosed(b: &GridBox, index: &SpatialIndex) -> bool {
+    (0..3).all(|axis| {
+        let others = [(axis + 1) % 3, (axis + 2) % 3];
+        [b.min[axis], b.max[axis]].iter().all(|&plane| {
+            // A thin slab just outside of the face
+            let mut slab = *b;
+            if plane == b.min[axis] {
+                slab.min[axis] = plane - 1;
+                slab.max[axis] = plane;
+            } else {
+                slab.min[axis] = plane;
+                slab.max[axis] = plane + 1;
+            }
+            let rects = index
+                .query(&slab)
+                .into_iter()
+                .map(|n| {
+                    (
+                        [n.min[others[0]], n.min[others[1]]],
+                        [n.max[others[0]], n.max[others[1]]],
+                    )
+                })
+                .collect::<Vec<_>>();
+            covered(
+                [b.min[others[0]], b.min[others[1]]],
+                [b.max[others[0]], b.max[others[1]]],
+                &rects,
+            )
+        })
+    })
+}
+
+/// Whether a part blocks the view and can't be walked into
+fn is_solid(item: &Item) -> bool {
+    matches!(item.properties.get("Transparency"), Some(Property::Float(t)) if *t == 0.)
+        && matches!(
+            item.properties.get("CanCollide"),
+            Some(Property::Bool(true))
+        )
+}
+
+fn add_solid_boxes(items: &[Item], scale: f32, index: &mut SpatialIndex) {
+    for item in items {
+        if is_solid(item) {
+            if let Some(b) = block_box(item, scale) {
+                index.insert(b);
+            }
+        }
+        add_solid_boxes(&item.children, scale, index);
+    }
+}
+
+/// Split bricks into the ones that can be seen or touched and the ones that
+/// can't, because they're single block Parts and every one of their faces is
+/// covered by solid block parts.
+pub fn cull_bricks(
+    bricks: Vec<ConvertedBrick>,
+    scale: f32,
+) -> (Vec<ConvertedBrick>, Vec<ConvertedBrick>) {
+    let mut index = SpatialIndex::default();
+    for brick in bricks.iter() {
+        add_solid_boxes(&brick.items, scale, &mut index);
+    }
+    bricks
+        .into_iter()
+        .partition(|brick| match &brick.items[..] {
+            [item] => !grid_box(item, scale).is_some_and(|b| enclosed(&b, &index)),
+            _ => true,
+        })
+}
+
+/// Split bricks into the first brick in each place and the ones that are
+/// exact copies of it, with the same UI name, position, angle and color.
+pub fn remove_duplicates(
+    bricks: Vec<ConvertedBrick>,
+) -> (Vec<ConvertedBrick>, Vec<ConvertedBrick>) {
+    let mut seen = HashSet::new();
+    bricks
+        .into_iter()
+        .partition(|brick| seen.insert(duplicate_key(brick)))
+}
+
+/// Bricks with the same key are copies of each other
+fn duplicate_key(brick: &ConvertedBrick) -> (String, [i64; 3], u8, u8) {
+    let (x, y, z) = brick.position;
+    (
+        brick.ui_name.clone(),
+        [x, y, z].map(|v| (v * GRID).round() as i64),
+        brick.angle,
+        brick.color_index,
+    )
+}
+
+fn add_block_boxes(items: &[Item], scale: f32, boxes: &mut Vec<GridBox>) {
+    for item in items {
+        if let Some(b) = block_box(item, scale) {
+            boxes.push(b);
+        }
+        add_block_boxes(&item.children, scale, boxes);
+    }
+}
+
+/// Pairs of bricks whose block parts overlap each other, other than bricks
+/// that are copies of each other. Copies of a brick count as one brick, so
+/// each overlap is only reported once.
+pub fn find_overlaps(bricks: &[ConvertedBrick], scale: f32) -> Vec<(BrickLocation, BrickLocation)> {
+    let mut index = SpatialIndex::default();
+    // The brick each box in the index belongs to
+    let mut owners = vec![];
+    let mut seen = HashSet::new();
+    for (i, brick) in bricks.iter().enumerate() {
+        if !seen.insert(duplicate_key(brick)) {
+            continue;
+        }
+        let mut boxes = vec![];
+        add_block_boxes(&brick.items, scale, &mut boxes);
+        for b in boxes {
+            index.insert(b);
+            owners.push(i);
+        }
+    }
+
+    let mut pairs = HashSet::new();
+    for (a, b) in index.boxes.iter().enumerate() {
+        for other in index.query_indices(b) {
+            let (first, second) = (owners[a], owners[other]);
+            if first < second {
+                pairs.insert((first, second));
+            }
+        }
+    }
+    let mut pairs = pairs.into_iter().collect::<Vec<_>>();
+    pairs.sort_unstable();
+    let location = |i: usize| BrickLocation {
+        ui_name: bricks[i].ui_name.clone(),
+        position: bricks[i].position,
+    };
+    pairs
+        .into_iter()
+        .map(|(first, second)| (location(first), location(second)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConvertOptions, Converter};
+
+    fn brick(ui_name: &str, position: (f32, f32, f32)) -> ConvertedBrick {
+        let colors = [(0.5, 0.5, 0.5, 1.); 64];
+        let brick = bl_save::Brick {
+            base: bl_save::BrickBase {
+                ui_name: ui_name.to_string(),
+                position,
+                angle: 0,
+                is_baseplate: false,
+                color_index: 0,
+                print: String::new(),
+                color_fx: 0,
+                shape_fx: 0,
+                raycasting: true,
+                collision: true,
+                rendering: true,
+            },
+            unknown_extra: vec![],
+        };
+        let items = Converter::new(ConvertOptions::default())
+            .convert_brick(&brick, &colors)
+            .unwrap();
+        ConvertedBrick {
+            owner: None,
+            ui_name: ui_name.to_string(),
+            position,
+            angle: 0,
+            color_index: 0,
+            items,
+        }
+    }
+
+    #[test]
+    fn merges_a_wall() {
+        // Two 1x1 bricks wide and two high
+        let bricks = vec![
+            brick("1x1", (0., 0., 0.3)),
+            brick("1x1", (0.5, 0., 0.3)),
+            brick("1x1", (0., 0., 0.9)),
+            brick("1x1", (0.5, 0., 0.9)),
+        ];
+        let merged = merge_bricks(bricks, 1.);
+        let items = merged
+            .into_iter()
+            .flat_map(|brick| brick.items)
+            .collect::<Vec<_>>();
+        assert_eq!(count_parts(&items), 1);
+        match (
+            items[0].properties.get("size"),
+            items[0].properties.get("CFrame"),
+        ) {
+            (Some(Property::Vector3(size)), Some(Property::CFrame(cframe))) => {
+                assert_eq!((size.x(), size.y(), size.z()), (2., 2.4, 1.));
+                assert_eq!(
+                    (cframe.vector.x(), cframe.vector.y(), cframe.vector.z()),
+                    (0.5, 1.2, 0.)
+                );
+            }
+            _ => panic!("merged part has no size or CFrame"),
+        }
+    }
+
+    #[test]
+    fn culls_the_middle_of_a_cube() {
+        let mut bricks = vec![];
+        for &x in &[0., 0.5, 1.] {
+            for &y in &[0., 0.5, 1.] {
+                for &z in &[0.3, 0.9, 1.5] {
+                    bricks.push(brick("1x1", (x, y, z)));
+                }
+            }
+        }
+        let (kept, culled) = cull_bricks(bricks, 1.);
+        assert_eq!(kept.len(), 26);
+        assert_eq!(culled.len(), 1);
+        assert_eq!(culled[0].position, (0.5, 0.5, 0.9));
+    }
+
+    #[test]
+    fn removes_duplicates() {
+        let bricks = vec![
+            brick("2x2", (0., 0., 0.3)),
+            brick("2x2", (0., 0., 0.3)),
+            brick("1x1", (0., 0., 0.3)),
+            brick("2x2", (0., 0., 0.9)),
+        ];
+        let (kept, duplicates) = remove_duplicates(bricks);
+        assert_eq!(
+            kept.iter()
+                .map(|brick| (brick.ui_name.as_str(), brick.position))
+                .collect::<Vec<_>>(),
+            [
+                ("2x2", (0., 0., 0.3)),
+                ("1x1", (0., 0., 0.3)),
+                ("2x2", (0., 0., 0.9)),
+            ]
+        );
+        assert_eq!(duplicates.len(), 1);
+    }
+
+    #[test]
+    fn finds_only_overlaps() {
+        let bricks = vec![
+            brick("2x2", (0., 0., 0.3)),
+            // Next to and on top of the first brick
+            brick("2x2", (1., 0., 0.3)),
+            brick("2x2", (0., 0., 0.9)),
+            // Inside of the first brick
+            brick("1x1", (0.25, 0.25, 0.3)),
+            // A copy of the first brick, which is a duplicate instead
+            brick("2x2", (0., 0., 0.3)),
+            // A copy of the second brick, which overlaps nothing more
+            brick("2x2", (1., 0., 0.3)),
+        ];
+        let overlaps = find_overlaps(&bricks, 1.)
+            .into_iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            overlaps,
+            [(
+                "2x2 at (0 0 0.3)".to_string(),
+                "1x1 at (0.25 0.25 0.3)".to_string()
+            )]
+        );
+    }
+}
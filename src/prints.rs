@@ -0,0 +1,110 @@
+//! Textures for Blockland prints, read from a mapping file.
+//!
+//! The mapping file is TOML with a key for every print name, as it's written
+//! in the save file. Values are either a Roblox asset id, a full content link,
+//! or a path to an image inside Roblox's content folder:
+//!
+//! ```toml
+//! "Letters/A" = 123456789
+//! "Letters/B" = "rbxassetid://123456790"
+//! "Letters/C" = "prints/letters/c.png"    # becomes rbxasset://prints/letters/c.png
+//! ```
+
+use crate::error::{Error, Result};
+
+use serde::Deserialize;
+
+use std::{collections::HashMap, fs, path::Path};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTexture {
+    AssetId(u64),
+    Path(String),
+}
+
+/// Textures to use for prints, by print name
+#[derive(Default)]
+pub struct PrintMap {
+    textures: HashMap<String, String>,
+}
+
+impl PrintMap {
+    pub fn new() -> Self {
+        PrintMap::default()
+    }
+
+    /// Load a mapping file, adding to or replacing the textures already loaded
+    pub fn load(&mut self, path: &Path) -> Result<()> {
+        let source = fs::read_to_string(path)?;
+        self.add_toml(&source, &path.display().to_string())
+    }
+
+    /// Add the textures in a TOML document. `origin` is used in error messages.
+    pub fn add_toml(&mut self, source: &str, origin: &str) -> Result<()> {
+        let raw: HashMap<String, RawTexture> =
+            toml::from_str(source).map_err(|e| Error::Definition {
+                origin: origin.to_string(),
+                reason: e.to_string(),
+            })?;
+        for (print, texture) in raw {
+            let texture = match texture {
+                RawTexture::AssetId(id) => format!("rbxassetid://{}", id),
+                RawTexture::Path(path) if path.contains("://") => path,
+                RawTexture::Path(path) => format!("rbxasset://{}", path.replace('\\', "/")),
+            };
+            self.textures.insert(print, texture);
+        }
+        Ok(())
+    }
+
+    /// The content link for a print
+    pub fn texture(&self, print: &str) -> Option<&str> {
+        self.textures.get(print).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn textures_are_content_links() {
+        let mut prints = PrintMap::new();
+        prints
+            .add_toml(
+                r#"
+                "Letters/A" = 123456789
+                "Letters/B" = "rbxassetid://123456790"
+                "Letters/C" = "prints/letters/c.png"
+                "Letters/D" = "prints\\letters\\d.png"
+                "#,
+                "test",
+            )
+            .unwrap();
+        assert_eq!(prints.texture("Letters/A"), Some("rbxassetid://123456789"));
+        assert_eq!(prints.texture("Letters/B"), Some("rbxassetid://123456790"));
+        assert_eq!(
+            prints.texture("Letters/C"),
+            Some("rbxasset://prints/letters/c.png")
+        );
+        assert_eq!(
+            prints.texture("Letters/D"),
+            Some("rbxasset://prints/letters/d.png")
+        );
+        assert_eq!(prints.texture("Letters/E"), None);
+
+        // Later files replace the textures of earlier ones
+        prints.add_toml(r#""Letters/A" = 42"#, "test").unwrap();
+        assert_eq!(prints.texture("Letters/A"), Some("rbxassetid://42"));
+        assert_eq!(prints.texture("Letters/B"), Some("rbxassetid://123456790"));
+    }
+
+    #[test]
+    fn invalid_files_say_where_they_came_from() {
+        match PrintMap::new().add_toml(r#""Letters/A" = true"#, "prints.toml") {
+            Err(Error::Definition { origin, .. }) => assert_eq!(origin, "prints.toml"),
+            _ => panic!("expected a definition error"),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use crate::error::{Error, Result};
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    io::{self, BufRead, Read},
+    rc::Rc,
+};
+
+/// Reads the bricks of a save file. Unlike `bl_save::Reader`, errors say which
+/// line of the file they happened on, and a malformed line doesn't take the
+/// brick before it down with it.
+pub struct SaveReader<R: BufRead> {
+    reader: bl_save::Reader<LineReader<R>>,
+    lines: Rc<LineState>,
+}
+
+impl<R: BufRead> SaveReader<R> {
+    /// Read the metadata of a save file
+    pub fn new(r: R) -> Result<Self> {
+        let lines = Rc::new(LineState::default());
+        let line_reader = LineReader {
+            inner: r,
+            line: Vec::new(),
+            consumed: 0,
+            lines: lines.clone(),
+        };
+        let reader = bl_save::Reader::new(line_reader).map_err(|e| lines.error(e))?;
+        // Everything after the metadata is brick data
+        lines.checking.set(true);
+        Ok(SaveReader { reader, lines })
+    }
+
+    /// The description of the save file
+    pub fn description(&self) -> &str {
+        self.reader.description()
+    }
+
+    /// The colorset used by bricks in the save file
+    pub fn colors(&self) -> &[(f32, f32, f32, f32); 64] {
+        self.reader.colors()
+    }
+
+    /// The claimed brick count, if available. Not guaranteed to be correct.
+    pub fn brick_count(&self) -> Option<usize> {
+        self.reader.brick_count()
+    }
+}
+
+impl<R: BufRead> Iterator for SaveReader<R> {
+    type Item = Result<bl_save::Brick>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // `bl_save::Reader` reads a line ahead to find the extras of a brick,
+        // so malformed lines it skipped past come after the brick it returned
+        // last
+        if let Some(e) = self.lines.malformed.borrow_mut().pop_front() {
+            return Some(Err(e));
+        }
+        match self.reader.next() {
+            Some(brick) => Some(brick.map_err(|e| self.lines.error(e))),
+            None => self.lines.malformed.borrow_mut().pop_front().map(Err),
+        }
+    }
+}
+
+#[derive(Default)]
+struct LineState {
+    /// Lines that have been read
+    read: Cell<usize>,
+    /// Whether lines are brick data, which is checked before
+    /// `bl_save::Reader` sees it
+    checking: Cell<bool>,
+    /// Brick data lines that couldn't be parsed, which were left out
+    malformed: RefCell<VecDeque<Error>>,
+}
+
+impl LineState {
+    /// Attach the line that was being read to a parse error
+    fn error(&self, e: io::Error) -> Error {
+        if e.kind() == io::ErrorKind::InvalidData {
+            Error::Parse {
+                line: self.read.get(),
+                source: e,
+            }
+        } else {
+            Error::Io(e)
+        }
+    }
+}
+
+/// Whether `bl_save` can parse a line of brick data: an extra line, the brick
+/// count, or a UI name and a quote followed by a space
+fn check_brick_line(line: &[u8]) -> io::Result<()> {
+    if line.starts_with(b"+-") || line.starts_with(b"Linecount ") {
+        return Ok(());
+    }
+    match line.iter().position(|b| *b == b'"') {
+        Some(quote) if line.get(quote + 1) == Some(&b' ') => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Invalid brick line",
+        )),
+    }
+}
+
+/// Hands a `BufRead` to `bl_save` one line at a time, counting lines and
+/// holding back brick data lines it can't parse
+struct LineReader<R> {
+    inner: R,
+    line: Vec<u8>,
+    /// How much of `line` has been consumed
+    consumed: usize,
+    lines: Rc<LineState>,
+}
+
+impl<R: BufRead> Read for LineReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LineReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.consumed == self.line.len() {
+            self.line.clear();
+            self.consumed = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                break;
+            }
+            let number = self.lines.read.get() + 1;
+            self.lines.read.set(number);
+            if self.lines.checking.get() {
+                let content = self
+                    .line
+                    .strip_suffix(b"\n")
+                    .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+                    .unwrap_or(&self.line);
+                if let Err(e) = check_brick_line(content) {
+                    self.lines.malformed.borrow_mut().push_back(Error::Parse {
+                        line: number,
+                        source: e,
+                    });
+                    self.line.clear();
+                }
+            }
+        }
+        Ok(&self.line[self.consumed..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consumed = (self.consumed + amt).min(self.line.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConvertOptions, Converter, ErrorPolicy};
+
+    /// A save file with one description line, a gray colorset and `bricks`,
+    /// which start on line 68
+    fn save(bricks: &str) -> String {
+        let mut save = String::from(
+            "This is a Blockland save file.  You probably shouldn't modify it cause you'll screw it up.\n1\nTest\n",
+        );
+        for _ in 0..64 {
+            save.push_str("0.5 0.5 0.5 1.000000\n");
+        }
+        save.push_str(bricks);
+        save
+    }
+
+    #[test]
+    fn malformed_lines_keep_the_brick_before_them() {
+        let save = save(
+            "Linecount 3\n\
+             1x1\" 0 0 0.3 0 1 0  0 0 1 1 1\n\
+             +-LIGHT Red\" 1\n\
+             this line is broken\n\
+             2x2\" 0 0 0.9 0 1 0  0 0 1 1 1\n\
+             1x2 0 0 1.5\n",
+        );
+        let reader = SaveReader::new(save.as_bytes()).unwrap();
+        assert_eq!(reader.brick_count(), Some(3));
+        let results = reader.collect::<Vec<_>>();
+        assert_eq!(results.len(), 4);
+        match &results[0] {
+            Ok(brick) => {
+                assert_eq!(brick.base.ui_name, "1x1");
+                assert_eq!(brick.unknown_extra, ["+-LIGHT Red\" 1"]);
+            }
+            Err(e) => panic!("first brick wasn't read: {}", e),
+        }
+        assert!(matches!(results[1], Err(Error::Parse { line: 71, .. })));
+        assert!(matches!(&results[2], Ok(brick) if brick.base.ui_name == "2x2"));
+        assert!(matches!(results[3], Err(Error::Parse { line: 73, .. })));
+    }
+
+    #[test]
+    fn errors_before_the_first_brick_have_line_numbers() {
+        let save = save("this line is broken\n");
+        assert!(matches!(
+            SaveReader::new(save.as_bytes()),
+            Err(Error::Parse { line: 68, .. })
+        ));
+    }
+
+    /// Two bricks around a line that isn't a brick
+    fn corrupt_save() -> String {
+        save(
+            "Linecount 2\n\
+             1x1\" 0 0 0.3 0 1 0  0 0 1 1 1\n\
+             1x1 0 0 0.9\n\
+             2x2\" 0 0 1.5 0 1 0  0 0 1 1 1\n",
+        )
+    }
+
+    fn converter(error_policy: ErrorPolicy) -> Converter {
+        Converter::new(ConvertOptions {
+            error_policy,
+            ..ConvertOptions::default()
+        })
+    }
+
+    #[test]
+    fn corrupt_lines_abort() {
+        let save = corrupt_save();
+        let reader = SaveReader::new(save.as_bytes()).unwrap();
+        match converter(ErrorPolicy::Abort).convert_reader(reader) {
+            Err(Error::Parse { line, .. }) => assert_eq!(line, 70),
+            Err(e) => panic!("expected a parse error, got {}", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn corrupt_lines_are_skipped_and_reported() {
+        let save = corrupt_save();
+        let reader = SaveReader::new(save.as_bytes()).unwrap();
+        let conversion = converter(ErrorPolicy::SkipAndReport)
+            .convert_reader(reader)
+            .unwrap();
+        // Both bricks are converted, the one before the corrupt line too
+        assert_eq!(conversion.items.len(), 2);
+        assert_eq!(conversion.report.skipped.len(), 1);
+        assert_eq!(
+            conversion.report.skipped[0].to_string(),
+            "Line 70: Invalid brick line"
+        );
+    }
+}
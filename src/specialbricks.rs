@@ -8,7 +8,11 @@ pub const TWO_PI: f32 = 2. * PI;
 
 pub const SPAWN_HEIGHT: f32 = 0.2;
 pub const WINDOW_RIM_WIDTH: f32 = 0.1;
+pub const POLE_WIDTH: f32 = 0.5;
+/// Vehicle spawns and checkpoints are plates
+pub const PLATE_HEIGHT: f32 = BRICK_HEIGHT / 3.;
 
+#[derive(Default)]
 pub struct SpecialBricksCache {
     cone2x2x2: Option<Item>,
     cone1x1: Option<Item>,
@@ -19,6 +23,12 @@ pub struct SpecialBricksCache {
     crest_end_25: Option<Item>,
     crest_corner_45: Option<Item>,
     crest_end_45: Option<Item>,
+    horse_ramp: Option<Item>,
+    pine_tree: Option<Item>,
+    music_brick: Option<Item>,
+    disc_2x2: Option<Item>,
+    vehicle_spawn: Option<Item>,
+    checkpoint: Option<Item>,
 }
 
 fn generate_cone(cone_size: f32) -> Item {
@@ -455,19 +465,317 @@ fn generate_crest_end(height: f32) -> Item {
     model
 }
 
+/// Move an item and everything in it
+fn translate(item: &mut Item, offset: Vector3) {
+    if let Some(Property::CFrame(cframe)) = item.properties.get_mut("CFrame") {
+        *cframe = *cframe + offset;
+    }
+    for child in item.children.iter_mut() {
+        translate(child, offset);
+    }
+}
+
+fn block(size: Vector3, position: Vector3) -> Item {
+    let mut part = Item::default("Part");
+    part.properties.insert("size", Property::Vector3(size));
+    part.properties.insert(
+        "CFrame",
+        Property::CFrame(CFrame {
+            vector: position,
+            rotation: Rotation3::identity(),
+        }),
+    );
+    part
+}
+
+/// A wedge that is tallest at its back (+Z) before it is rotated
+fn wedge(size: Vector3, position: Vector3, rotation: Rotation3<f32>) -> Item {
+    let mut part = Item::default("WedgePart");
+    part.properties.insert("size", Property::Vector3(size));
+    part.properties.insert(
+        "CFrame",
+        Property::CFrame(CFrame {
+            vector: position,
+            rotation,
+        }),
+    );
+    part
+}
+
+/// The parts of a ramp around the origin that slopes up from its front (-Z):
+/// a wedge `run` deep at the front standing on a `lip` thick block, and a
+/// wall filling the rest of `size` behind them. Ramps of stock bricks, terrain
+/// bricks and the horse ramp are all built from these.
+pub fn ramp_parts(size: Vector3, run: f32, lip: f32) -> Vec<Item> {
+    let wall = size.z() - run;
+    let front = -wall / 2.;
+    let mut parts = vec![wedge(
+        Vector3::new(size.x(), size.y() - lip, run),
+        Vector3::new(0., lip / 2., front),
+        Rotation3::identity(),
+    )];
+    if lip > 0. {
+        parts.push(block(
+            Vector3::new(size.x(), lip, run),
+            Vector3::new(0., (lip - size.y()) / 2., front),
+        ));
+    }
+    if wall > 0. {
+        parts.push(block(
+            Vector3::new(size.x(), size.y(), wall),
+            Vector3::new(0., 0., run / 2.),
+        ));
+    }
+    parts
+}
+
+fn cylinder(size: Vector3, position: Vector3) -> Item {
+    let mut part = block(size, position);
+    part.children.push(Item::default("CylinderMesh"));
+    part
+}
+
+/// How many bricks tall the arch of each length is
+pub fn arch_height(length: u8) -> Option<f32> {
+    match length {
+        3 | 4 => Some(1.),
+        6 => Some(2.),
+        8 => Some(3.),
+        _ => None,
+    }
+}
+
+/// An arch along the Z axis: a pillar at each end, a beam one plate thick
+/// across the top, and a wedge on each side of the opening under the beam
+pub fn generate_arch(length: u8, height: f32) -> Item {
+    let mut model = Item::default("Model");
+
+    let length = length as f32;
+    let height = height * BRICK_HEIGHT;
+    let beam_height = BRICK_HEIGHT / 3.;
+    let opening_height = height - beam_height;
+    let opening = length - 2.;
+
+    let beam = block(
+        Vector3::new(1., beam_height, length),
+        Vector3::new(0., (height - beam_height) / 2., 0.),
+    );
+
+    let pillar = |z: f32| {
+        block(
+            Vector3::new(1., opening_height, 1.),
+            Vector3::new(0., -beam_height / 2., z),
+        )
+    };
+
+    // Upside down wedges that are thickest against the pillars
+    let wedge_height = opening_height / 2.;
+    let create_wedge = |z: f32, rotation: Rotation3<f32>| {
+        wedge(
+            Vector3::new(1., wedge_height, opening / 2.),
+            Vector3::new(0., height / 2. - beam_height - wedge_height / 2., z),
+            rotation,
+        )
+    };
+
+    model.children.push(pillar((1. - length) / 2.));
+    model.children.push(pillar((length - 1.) / 2.));
+    model.children.push(create_wedge(
+        -opening / 4.,
+        Rotation3::from_scaled_axis(NVector3::x() * PI),
+    ));
+    model.children.push(create_wedge(
+        opening / 4.,
+        Rotation3::from_scaled_axis(NVector3::z() * PI),
+    ));
+    model.children.push(beam);
+    model
+}
+
+/// A thin pole in the middle of a 1x1 brick, `height` bricks tall
+pub fn generate_pole(height: f32) -> Item {
+    cylinder(
+        Vector3::new(POLE_WIDTH, height * BRICK_HEIGHT, POLE_WIDTH),
+        Vector3::new(0., 0., 0.),
+    )
+}
+
+/// A long shallow ramp, 4 studs wide and 6 long, that horses can walk up
+fn generate_horse_ramp() -> Item {
+    let mut model = Item::default("Model");
+    model.children = ramp_parts(
+        Vector3::new(4., 2. * BRICK_HEIGHT, 6.),
+        6.,
+        WEDGE_LIP_SIZE * BRICK_HEIGHT,
+    );
+    model
+}
+
+/// A trunk with three cones of leaves stacked on it, 6 studs wide and 9
+/// bricks tall
+fn generate_pine_tree() -> Item {
+    let mut model = Item::default("Model");
+
+    let bottom = -4.5 * BRICK_HEIGHT;
+    let trunk = cylinder(
+        Vector3::new(1., 2. * BRICK_HEIGHT, 1.),
+        Vector3::new(0., bottom + BRICK_HEIGHT, 0.),
+    );
+    model.children.push(trunk);
+
+    // Each cone starts halfway up the one below it
+    let mut cone_bottom = bottom + 2. * BRICK_HEIGHT;
+    for &size in &[6., 4., 2.] {
+        let mut cone = generate_cone(size);
+        let cone_height = size * BRICK_HEIGHT;
+        translate(
+            &mut cone,
+            Vector3::new(0., cone_bottom + cone_height / 2., 0.),
+        );
+        model.children.append(&mut cone.children);
+        cone_bottom += cone_height / 2.;
+    }
+    model
+}
+
+/// The sloped shapes of ModTer terrain bricks, named like `4x Wedge` or
+/// `8x Corner B Steep`. Their cubes are plain blocks, converted like any
+/// other cube.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TerrainShape {
+    /// Slopes up from the front to the back
+    Wedge,
+    /// Slopes up over the front half and is flat over the back half
+    Ramp,
+    /// An outside corner, sloping down from the back right to the front and
+    /// the left
+    CornerA,
+    /// An inside corner, sloping up from the front left to the back and the
+    /// right
+    CornerB,
+    /// An outside corner upside down, for overhangs
+    CornerC,
+    /// An inside corner upside down, for overhangs
+    CornerD,
+}
+
+impl TerrainShape {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Wedge" => Some(TerrainShape::Wedge),
+            "Ramp" => Some(TerrainShape::Ramp),
+            "Corner A" | "CornerA" => Some(TerrainShape::CornerA),
+            "Corner B" | "CornerB" => Some(TerrainShape::CornerB),
+            "Corner C" | "CornerC" => Some(TerrainShape::CornerC),
+            "Corner D" | "CornerD" => Some(TerrainShape::CornerD),
+            _ => None,
+        }
+    }
+}
+
+/// Turn an item and everything in it upside down around the origin, keeping
+/// its front at the front
+fn turn_upside_down(item: &mut Item) {
+    let flip = CFrame {
+        vector: Vector3::new(0., 0., 0.),
+        rotation: Rotation3::from_scaled_axis(NVector3::z() * PI),
+    };
+    if let Some(Property::CFrame(cframe)) = item.properties.get_mut("CFrame") {
+        *cframe = flip * *cframe;
+    }
+    for child in item.children.iter_mut() {
+        turn_upside_down(child);
+    }
+}
+
+/// A terrain brick covering `size` by `size` studs. Terrain bricks are as tall
+/// as they are wide, or twice as tall if they are `steep`.
+pub fn generate_terrain(shape: TerrainShape, size: f32, steep: bool) -> Item {
+    let height = if steep { 2. * size } else { size };
+    let full = Vector3::new(size, height, size);
+    let center = Vector3::new(0., 0., 0.);
+    // Ramps without a lip, sloping up over the front `run` studs
+    let ramp = |run: f32| {
+        let mut model = Item::default("Model");
+        model.children = ramp_parts(full, run, 0.);
+        model
+    };
+    // Turns a wedge to be tallest at its right (+X)
+    let to_right = Rotation3::from_scaled_axis(NVector3::y() * FRAC_PI_2);
+
+    let outside_corner = || {
+        let mut corner = Item::default("CornerWedgePart");
+        corner.properties.insert("size", Property::Vector3(full));
+        corner.properties.insert(
+            "CFrame",
+            Property::CFrame(CFrame {
+                vector: center,
+                rotation: Rotation3::from_scaled_axis(NVector3::y() * -FRAC_PI_2),
+            }),
+        );
+        corner
+    };
+    // Where two wedges cross, the taller one wins, which leaves a valley
+    // along the diagonal
+    let inside_corner = || {
+        let mut model = Item::default("Model");
+        model
+            .children
+            .push(wedge(full, center, Rotation3::identity()));
+        model.children.push(wedge(full, center, to_right));
+        model
+    };
+
+    match shape {
+        TerrainShape::Wedge => ramp(size),
+        TerrainShape::Ramp => ramp(size / 2.),
+        TerrainShape::CornerA => outside_corner(),
+        TerrainShape::CornerB => inside_corner(),
+        TerrainShape::CornerC => {
+            let mut corner = outside_corner();
+            turn_upside_down(&mut corner);
+            corner
+        }
+        TerrainShape::CornerD => {
+            let mut corner = inside_corner();
+            turn_upside_down(&mut corner);
+            corner
+        }
+    }
+}
+
+/// An 8x8 plate named and tagged `VehicleSpawn`
+fn generate_vehicle_spawn() -> Item {
+    let mut spawn = block(Vector3::new(8., PLATE_HEIGHT, 8.), Vector3::new(0., 0., 0.));
+    spawn
+        .properties
+        .insert("Name", Property::String("VehicleSpawn".to_string()));
+    spawn.add_tag("VehicleSpawn");
+    spawn
+}
+
+/// The Attachment on top of a vehicle spawn that the VehicleSeat of a vehicle
+/// can be lined up with. Attachments are placed relative to their part, so
+/// this is added after the vehicle spawn is placed.
+pub fn vehicle_spawn_attachment(scale: f32) -> Item {
+    let mut attachment = Item::new("Attachment");
+    attachment.properties.insert(
+        "Name",
+        Property::String("VehicleSpawnAttachment".to_string()),
+    );
+    attachment.properties.insert(
+        "CFrame",
+        Property::CFrame(CFrame {
+            vector: Vector3::new(0., PLATE_HEIGHT / 2. * scale, 0.),
+            rotation: Rotation3::identity(),
+        }),
+    );
+    attachment
+}
+
 impl SpecialBricksCache {
     pub fn new() -> Self {
-        SpecialBricksCache {
-            cone2x2x2: None,
-            cone1x1: None,
-            castle_wall: None,
-            spawn_point: None,
-            window_1x4x3: None,
-            crest_corner_25: None,
-            crest_end_25: None,
-            crest_corner_45: None,
-            crest_end_45: None,
-        }
+        Self::default()
     }
 
     pub fn cone2x2x2(&mut self) -> Item {
@@ -568,4 +876,87 @@ impl SpecialBricksCache {
             }
         }
     }
+
+    pub fn horse_ramp(&mut self) -> Item {
+        match &self.horse_ramp {
+            Some(ramp) => ramp.clone(),
+            None => {
+                let ramp = generate_horse_ramp();
+                self.horse_ramp = Some(ramp.clone());
+                ramp
+            }
+        }
+    }
+
+    pub fn pine_tree(&mut self) -> Item {
+        match &self.pine_tree {
+            Some(tree) => tree.clone(),
+            None => {
+                let tree = generate_pine_tree();
+                self.pine_tree = Some(tree.clone());
+                tree
+            }
+        }
+    }
+
+    /// A plain 1x1 brick, the music itself isn't converted
+    pub fn music_brick(&mut self) -> Item {
+        match &self.music_brick {
+            Some(brick) => brick.clone(),
+            None => {
+                let brick = block(Vector3::new(1., BRICK_HEIGHT, 1.), Vector3::new(0., 0., 0.));
+                self.music_brick = Some(brick.clone());
+                brick
+            }
+        }
+    }
+
+    /// A round plate
+    pub fn disc_2x2(&mut self) -> Item {
+        match &self.disc_2x2 {
+            Some(disc) => disc.clone(),
+            None => {
+                let disc = cylinder(
+                    Vector3::new(2., BRICK_HEIGHT / 3., 2.),
+                    Vector3::new(0., 0., 0.),
+                );
+                self.disc_2x2 = Some(disc.clone());
+                disc
+            }
+        }
+    }
+
+    pub fn vehicle_spawn(&mut self) -> Item {
+        match &self.vehicle_spawn {
+            Some(spawn) => spawn.clone(),
+            None => {
+                let spawn = generate_vehicle_spawn();
+                self.vehicle_spawn = Some(spawn.clone());
+                spawn
+            }
+        }
+    }
+
+    /// A 4x4 plate that players spawn on
+    pub fn checkpoint(&mut self) -> Item {
+        match &self.checkpoint {
+            Some(checkpoint) => checkpoint.clone(),
+            None => {
+                let mut checkpoint = Item::default("SpawnLocation");
+                checkpoint.properties.insert(
+                    "size",
+                    Property::Vector3(Vector3::new(4., PLATE_HEIGHT, 4.)),
+                );
+                checkpoint.properties.insert(
+                    "CFrame",
+                    Property::CFrame(CFrame {
+                        vector: Vector3::new(0., 0., 0.),
+                        rotation: Rotation3::identity(),
+                    }),
+                );
+                self.checkpoint = Some(checkpoint.clone());
+                checkpoint
+            }
+        }
+    }
 }
@@ -1,4 +1,7 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
 use uuid::Uuid;
 
 #[derive(Clone, Copy)]
@@ -73,9 +76,9 @@ impl std::ops::Div<f32> for Vector3 {
     }
 }
 
-impl ToString for Vector3 {
-    fn to_string(&self) -> String {
-        format!("<X>{}</X><Y>{}</Y><Z>{}</Z>", self.0.x, self.0.y, self.0.z)
+impl fmt::Display for Vector3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<X>{}</X><Y>{}</Y><Z>{}</Z>", self.0.x, self.0.y, self.0.z)
     }
 }
 
@@ -99,13 +102,41 @@ impl From<(f32, f32, f32, f32)> for Color3 {
     }
 }
 
-impl ToString for Color3 {
-    fn to_string(&self) -> String {
-        (((self.a as u32) << 24)
-            + ((self.r as u32) << 16)
-            + ((self.g as u32) << 8)
-            + (self.b as u32))
-            .to_string()
+impl fmt::Display for Color3 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            ((self.a as u32) << 24)
+                + ((self.r as u32) << 16)
+                + ((self.g as u32) << 8)
+                + (self.b as u32)
+        )
+    }
+}
+
+/// A color with float components from 0 to 1, used by properties like
+/// `PointLight.Color`
+#[derive(Clone, Copy)]
+pub struct Color3Float {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl From<Color3> for Color3Float {
+    fn from(color: Color3) -> Color3Float {
+        Color3Float {
+            r: color.r as f32 / 255.,
+            g: color.g as f32 / 255.,
+            b: color.b as f32 / 255.,
+        }
+    }
+}
+
+impl fmt::Display for Color3Float {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<R>{}</R><G>{}</G><B>{}</B>", self.r, self.g, self.b)
     }
 }
 
@@ -115,6 +146,42 @@ pub struct CFrame {
     pub rotation: nalgebra::Rotation3<f32>,
 }
 
+impl CFrame {
+    /// The transform that undoes this one
+    pub fn inverse(&self) -> CFrame {
+        let rotation = self.rotation.inverse();
+        CFrame {
+            vector: Vector3(-(rotation * self.vector.0)),
+            rotation,
+        }
+    }
+
+    /// Transform a point from the space of this CFrame to world space
+    pub fn point_to_world_space(&self, point: Vector3) -> Vector3 {
+        Vector3(self.rotation * point.0 + self.vector.0)
+    }
+}
+
+/// Composes two rigid transforms like Roblox's `CFrame * CFrame`: `self` is
+/// applied after `other`, so `other` is relative to `self`
+impl std::ops::Mul for CFrame {
+    type Output = Self;
+
+    fn mul(self, other: CFrame) -> Self {
+        CFrame {
+            vector: self.point_to_world_space(other.vector),
+            rotation: self.rotation * other.rotation,
+        }
+    }
+}
+
+impl std::ops::MulAssign for CFrame {
+    fn mul_assign(&mut self, other: CFrame) {
+        *self = *self * other;
+    }
+}
+
+/// Only moves the position, the rotation of `other` is ignored
 impl std::ops::Add for CFrame {
     type Output = Self;
 
@@ -134,6 +201,7 @@ impl std::ops::AddAssign for CFrame {
     }
 }
 
+/// Scales the position on each axis, the rotation is kept
 impl std::ops::MulAssign<Vector3> for CFrame {
     fn mul_assign(&mut self, other: Vector3) {
         self.vector.0.x *= other.0.x;
@@ -164,9 +232,10 @@ impl std::ops::Sub<Vector3> for CFrame {
     }
 }
 
-impl ToString for CFrame {
-    fn to_string(&self) -> String {
-        format!(
+impl fmt::Display for CFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
             "<X>{}</X>
 <Y>{}</Y>
 <Z>{}</Z>
@@ -198,20 +267,106 @@ impl ToString for CFrame {
 #[derive(Clone, Copy)]
 pub struct PhysicalProperties(pub bool);
 
-impl ToString for PhysicalProperties {
-    fn to_string(&self) -> String {
-        format!("<CustomPhysics>{}</CustomPhysics>", self.0)
+impl fmt::Display for PhysicalProperties {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<CustomPhysics>{}</CustomPhysics>", self.0)
     }
 }
 
+/// A link to an asset, such as `rbxassetid://123`
+#[derive(Clone)]
+pub struct Content(pub String);
+
+/// Text that Studio doesn't show in the properties window, like the source
+/// of a Script
 #[derive(Clone)]
+pub struct ProtectedString(pub String);
+
+/// A reference to another item, or to nothing
+#[derive(Clone)]
+pub struct Ref(pub Option<RbxUuid>);
+
+/// A CFrame that can be left unset, like the WorldPivot of a Model
+#[derive(Clone, Copy)]
+pub struct OptionalCFrame(pub Option<CFrame>);
+
+/// CollectionService tags
+#[derive(Clone, Default)]
+pub struct Tags(pub Vec<String>);
+
+impl Tags {
+    /// Tags are stored separated by null bytes
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.join("\0").into_bytes()
+    }
+}
+
+/// The value of an attribute
+#[derive(Clone)]
+pub enum AttributeValue {
+    String(String),
+    Bool(bool),
+    Number(f64),
+}
+
+/// Attributes, by name
+#[derive(Clone, Default)]
+pub struct Attributes(pub BTreeMap<String, AttributeValue>);
+
+impl Attributes {
+    /// Encode the attributes like the `AttributesSerialize` property
+    pub fn to_bytes(&self) -> Vec<u8> {
+        fn write_string(bytes: &mut Vec<u8>, s: &str) {
+            bytes.extend_from_slice(&(s.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(s.as_bytes());
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.0.len() as u32).to_le_bytes());
+        for (name, value) in self.0.iter() {
+            write_string(&mut bytes, name);
+            match value {
+                AttributeValue::String(s) => {
+                    bytes.push(0x02);
+                    write_string(&mut bytes, s);
+                }
+                AttributeValue::Bool(b) => {
+                    bytes.push(0x03);
+                    bytes.push(*b as u8);
+                }
+                AttributeValue::Number(n) => {
+                    bytes.push(0x06);
+                    bytes.extend_from_slice(&n.to_le_bytes());
+                }
+            }
+        }
+        bytes
+    }
+}
+
+/// A face of a part
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum NormalId {
+    Right = 0,
+    Top = 1,
+    Back = 2,
+    Left = 3,
+    Bottom = 4,
+    Front = 5,
+}
+
+impl NormalId {
+    pub fn token(self) -> u32 {
+        self as u32
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
 pub struct RbxUuid(pub Uuid);
 
-impl ToString for RbxUuid {
-    fn to_string(&self) -> String {
-        let mut s = "RBX".to_string();
-        s.push_str(&self.0.to_simple_ref().to_string());
-        s
+impl fmt::Display for RbxUuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RBX{}", self.0.to_simple_ref())
     }
 }
 
@@ -224,6 +379,49 @@ pub struct Item {
 }
 
 impl Item {
+    /// An item with no properties set
+    pub fn new(class: &'static str) -> Item {
+        Item {
+            class,
+            referent: RbxUuid(Uuid::new_v4()),
+            properties: HashMap::new(),
+            children: vec![],
+        }
+    }
+
+    /// Add a CollectionService tag
+    pub fn add_tag(&mut self, tag: &str) {
+        let tags = self
+            .properties
+            .entry("Tags")
+            .or_insert_with(|| Property::Tags(Tags::default()));
+        if let Property::Tags(tags) = tags {
+            if !tags.0.iter().any(|t| t == tag) {
+                tags.0.push(tag.to_string());
+            }
+        }
+    }
+
+    /// Set an attribute, replacing any with the same name
+    pub fn set_attribute(&mut self, name: &str, value: AttributeValue) {
+        let attributes = self
+            .properties
+            .entry("AttributesSerialize")
+            .or_insert_with(|| Property::Attributes(Attributes::default()));
+        if let Property::Attributes(attributes) = attributes {
+            attributes.0.insert(name.to_string(), value);
+        }
+    }
+
+    /// Give the item and its children new referents, so that copies of an
+    /// item can be told apart
+    pub fn renew_referents(&mut self) {
+        self.referent = RbxUuid(Uuid::new_v4());
+        for child in self.children.iter_mut() {
+            child.renew_referents();
+        }
+    }
+
     pub fn default(class: &'static str) -> Item {
         let mut properties: HashMap<&'static str, Property> = HashMap::new();
         vec![
@@ -280,18 +478,109 @@ impl Item {
     }
 }
 
-impl ToString for Item {
-    fn to_string(&self) -> String {
-        format!(
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
             "<Item class=\"{class}\" referent=\"{referent}\"><Properties>{props}</Properties>{children}</Item>",
             class=self.class,
-            referent=self.referent.to_string(),
+            referent=self.referent,
             props=self.properties.iter().map(|(k, v)| v.property_to_string(k)).collect::<Vec<_>>().join("\n"),
             children=self.children.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n")
         )
     }
 }
 
+/// Values that can be written inside of an XML property tag
+trait XmlValue {
+    fn xml_value(&self) -> String;
+}
+
+macro_rules! impl_xml_value_for_display {
+    ($($t:ty),*) => {
+        $(impl XmlValue for $t {
+            fn xml_value(&self) -> String {
+                self.to_string()
+            }
+        })*
+    };
+}
+
+impl_xml_value_for_display!(
+    bool,
+    f32,
+    u32,
+    i64,
+    CFrame,
+    Color3,
+    Color3Float,
+    PhysicalProperties,
+    Vector3
+);
+
+impl XmlValue for Content {
+    fn xml_value(&self) -> String {
+        format!("<url>{}</url>", escape_xml(&self.0))
+    }
+}
+
+impl XmlValue for ProtectedString {
+    fn xml_value(&self) -> String {
+        format!("<![CDATA[{}]]>", self.0.replace("]]>", "]]]]><![CDATA[>"))
+    }
+}
+
+impl XmlValue for Ref {
+    fn xml_value(&self) -> String {
+        match &self.0 {
+            Some(referent) => referent.to_string(),
+            None => "null".to_string(),
+        }
+    }
+}
+
+impl XmlValue for OptionalCFrame {
+    fn xml_value(&self) -> String {
+        match &self.0 {
+            Some(cframe) => format!("<CFrame>{}</CFrame>", cframe),
+            None => String::new(),
+        }
+    }
+}
+
+impl XmlValue for Tags {
+    fn xml_value(&self) -> String {
+        base64::encode(&self.to_bytes())
+    }
+}
+
+impl XmlValue for Attributes {
+    fn xml_value(&self) -> String {
+        base64::encode(&self.to_bytes())
+    }
+}
+
+impl XmlValue for String {
+    fn xml_value(&self) -> String {
+        escape_xml(self)
+    }
+}
+
+pub fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 macro_rules! define_property_enum {
     ($($tag:expr => $t:ident($backing:ty);)*) => {
         #[allow(dead_code)]
@@ -301,6 +590,13 @@ macro_rules! define_property_enum {
         }
 
         impl Property {
+            /// The name XML files give the type of this property
+            pub fn type_name(&self) -> &'static str {
+                match self {
+                    $(Property::$t(_) => $tag),*
+                }
+            }
+
             pub fn property_to_string(&self, prop_name: &str) -> String {
                 match self {
                     $(Property::$t(v) =>
@@ -308,7 +604,7 @@ macro_rules! define_property_enum {
                             "<{t} name=\"{n}\">{v}</{t}>",
                             t = $tag,
                             n = prop_name,
-                            v = v.to_string()
+                            v = v.xml_value()
                         )
                     ),*
                 }
@@ -323,8 +619,110 @@ define_property_enum! {
     "token" => Token(u32);
     "int" => Int(i64);
     "CoordinateFrame" => CFrame(CFrame);
+    "OptionalCoordinateFrame" => OptionalCFrame(OptionalCFrame);
     "Color3uint8" => Color3(Color3);
+    "Color3" => Color3Float(Color3Float);
+    "Content" => Content(Content);
     "PhysicalProperties" => PhysProps(PhysicalProperties);
+    "ProtectedString" => ProtectedString(ProtectedString);
+    "Ref" => Ref(Ref);
+    "BinaryString" => Tags(Tags);
+    "BinaryString" => Attributes(Attributes);
     "string" => String(String);
     "Vector3" => Vector3(Vector3);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::Rotation3;
+
+    use std::f32::consts::FRAC_PI_2;
+
+    fn assert_close(actual: Vector3, expected: (f32, f32, f32)) {
+        let expected = nalgebra::Vector3::new(expected.0, expected.1, expected.2);
+        assert!(
+            (actual.0 - expected).norm() < 1e-5,
+            "expected {:?}, got {:?}",
+            expected,
+            actual.0
+        );
+    }
+
+    /// Turned a quarter to the right around Y, like a brick with angle 1
+    fn turned(x: f32, y: f32, z: f32) -> CFrame {
+        CFrame {
+            vector: Vector3::new(x, y, z),
+            rotation: Rotation3::from_euler_angles(0., -FRAC_PI_2, 0.),
+        }
+    }
+
+    #[test]
+    fn composition_applies_the_parent_rotation() {
+        // A child one stud in front of its parent, tipped onto its side
+        let child = CFrame {
+            vector: Vector3::new(0., 0., -1.),
+            rotation: Rotation3::from_euler_angles(0., 0., FRAC_PI_2),
+        };
+        let world = turned(10., 2., 0.) * child;
+        // In front is +X once turned, and the child's rotation comes after
+        // the parent's
+        assert_close(world.vector, (11., 2., 0.));
+        assert_close(
+            Vector3(world.rotation * nalgebra::Vector3::y()),
+            (0., 0., -1.),
+        );
+
+        let mut composed = turned(10., 2., 0.);
+        composed *= child;
+        assert_close(composed.vector, (11., 2., 0.));
+
+        // Composing is associative but not commutative
+        let other = turned(0., 0., 5.);
+        let left = (turned(10., 2., 0.) * child) * other;
+        let right = turned(10., 2., 0.) * (child * other);
+        assert_close(
+            left.vector,
+            (right.vector.x(), right.vector.y(), right.vector.z()),
+        );
+        assert!((left.rotation.matrix() - right.rotation.matrix()).norm() < 1e-5);
+        let swapped = child * turned(10., 2., 0.);
+        assert!((swapped.vector.0 - world.vector.0).norm() > 1.);
+    }
+
+    #[test]
+    fn inverse_round_trips() {
+        let cframe = turned(3., -1., 7.)
+            * CFrame {
+                vector: Vector3::new(0.5, 0., 2.),
+                rotation: Rotation3::from_euler_angles(0.3, 0.2, 0.1),
+            };
+        let point = Vector3::new(-4., 5., 6.);
+        let there = cframe.point_to_world_space(point);
+        assert_close(cframe.inverse().point_to_world_space(there), (-4., 5., 6.));
+
+        let identity = cframe * cframe.inverse();
+        assert_close(identity.vector, (0., 0., 0.));
+        assert!((identity.rotation.matrix() - nalgebra::Matrix3::identity()).norm() < 1e-5);
+        let identity = cframe.inverse() * cframe;
+        assert_close(identity.vector, (0., 0., 0.));
+    }
+
+    #[test]
+    fn optional_cframes_are_written_as_optional() {
+        let pivot = Property::OptionalCFrame(OptionalCFrame(Some(CFrame {
+            vector: Vector3::new(1., 2., 3.),
+            rotation: nalgebra::Rotation3::identity(),
+        })));
+        let xml = pivot.property_to_string("WorldPivot");
+        assert!(xml.starts_with("<OptionalCoordinateFrame name=\"WorldPivot\"><CFrame><X>1</X>"));
+        assert!(xml.ends_with("<R22>1</R22></CFrame></OptionalCoordinateFrame>"));
+
+        let unset = Property::OptionalCFrame(OptionalCFrame(None));
+        assert_eq!(
+            unset.property_to_string("WorldPivot"),
+            "<OptionalCoordinateFrame name=\"WorldPivot\"></OptionalCoordinateFrame>"
+        );
+    }
+}
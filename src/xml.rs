@@ -1,3 +1,33 @@
+use crate::types::Item;
+
+use std::io::{self, Write};
+
+/// Write `items` as an XML place (.rbxlx), parented to Workspace
+pub fn write_place<W: Write>(w: &mut W, items: Vec<Item>) -> io::Result<()> {
+    write!(w, "{}", START_XML)?;
+    for item in items {
+        writeln!(w, "{}", item)?;
+    }
+    write!(w, "{}", END_XML)
+}
+
+/// Write `model` as the only top level item of an XML model (.rbxmx)
+pub fn write_model<W: Write>(w: &mut W, model: &Item) -> io::Result<()> {
+    write!(w, "{}", START_MODEL_XML)?;
+    writeln!(w, "{}", model)?;
+    write!(w, "{}", END_MODEL_XML)
+}
+
+pub const START_MODEL_XML: &str = r#"
+<roblox xmlns:xmime="http://www.w3.org/2005/05/xmlmime" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:noNamespaceSchemaLocation="http://www.roblox.com/roblox.xsd" version="4">
+	<External>null</External>
+	<External>nil</External>
+"#;
+
+pub const END_MODEL_XML: &str = r#"
+</roblox>
+"#;
+
 pub const START_XML: &str = r#"
 <roblox xmlns:xmime="http://www.w3.org/2005/05/xmlmime" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:noNamespaceSchemaLocation="http://www.roblox.com/roblox.xsd" version="4">
 	<External>null</External>